@@ -0,0 +1,140 @@
+use anyhow::anyhow;
+use database::entity::collection::{
+    ActiveModel as CollectionActiveModel, Column as CollectionColumn, Entity as Collection,
+    Model as CollectionModel,
+};
+use database::entity::collection_item::{
+    ActiveModel as CollectionItemActiveModel, Column as CollectionItemColumn,
+    Entity as CollectionItem,
+};
+use database::entity::generated_image::Entity as GeneratedImage;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, Set, Statement, TransactionTrait,
+};
+
+/// Slugs end up in shareable urls, so keep them to lowercase letters, digits
+/// and dashes.
+pub fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= 64
+        && !slug.starts_with('-')
+        && !slug.ends_with('-')
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+async fn find_collection(db: &DatabaseConnection, slug: &str) -> anyhow::Result<CollectionModel> {
+    Collection::find()
+        .filter(CollectionColumn::Slug.eq(slug))
+        .one(db)
+        .await?
+        .ok_or_else(|| anyhow!("No collection named {slug}"))
+}
+
+pub async fn create_collection(
+    db: &DatabaseConnection,
+    slug: &str,
+    name: &str,
+    description: Option<String>,
+) -> anyhow::Result<CollectionModel> {
+    if !is_valid_slug(slug) {
+        return Err(anyhow!(
+            "Invalid slug {slug}, use lowercase letters, digits and dashes"
+        ));
+    }
+
+    let collection = CollectionActiveModel {
+        slug: Set(slug.to_string()),
+        name: Set(name.to_string()),
+        description: Set(description),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(collection)
+}
+
+/// Appends generated images to the end of a collection, skipping images that
+/// are already in it. Returns the number of images added.
+pub async fn add_to_collection(
+    db: &DatabaseConnection,
+    slug: &str,
+    generated_image_ids: &[i32],
+) -> anyhow::Result<u64> {
+    let collection = find_collection(db, slug).await?;
+    let txn = db.begin().await?;
+
+    let last_position: Option<i32> = CollectionItem::find()
+        .select_only()
+        .column(CollectionItemColumn::Position)
+        .filter(CollectionItemColumn::CollectionId.eq(collection.id))
+        .order_by_desc(CollectionItemColumn::Position)
+        .into_tuple()
+        .one(&txn)
+        .await?;
+    let first_position = last_position.map(|position| position + 1).unwrap_or(0);
+
+    let mut added = 0;
+    for (position, id) in (first_position..).zip(generated_image_ids) {
+        if GeneratedImage::find_by_id(*id).one(&txn).await?.is_none() {
+            return Err(anyhow!("No generated image with id {id}"));
+        }
+
+        added += CollectionItem::insert(CollectionItemActiveModel {
+            collection_id: Set(collection.id),
+            generated_image_id: Set(*id),
+            position: Set(position),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                CollectionItemColumn::CollectionId,
+                CollectionItemColumn::GeneratedImageId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(&txn)
+        .await?;
+    }
+
+    txn.commit().await?;
+    Ok(added)
+}
+
+/// Removes an image and renumbers the rest so positions stay contiguous,
+/// closing any gaps left by generated images deleted since.
+pub async fn remove_from_collection(
+    db: &DatabaseConnection,
+    slug: &str,
+    generated_image_id: i32,
+) -> anyhow::Result<bool> {
+    let collection = find_collection(db, slug).await?;
+    let txn = db.begin().await?;
+    let result = CollectionItem::delete_many()
+        .filter(CollectionItemColumn::CollectionId.eq(collection.id))
+        .filter(CollectionItemColumn::GeneratedImageId.eq(generated_image_id))
+        .exec(&txn)
+        .await?;
+
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"UPDATE collection_item
+        SET position = numbered.position
+        FROM (
+            SELECT id, (row_number() OVER (ORDER BY position, id) - 1)::integer AS position
+            FROM collection_item
+            WHERE collection_id = $1
+        ) AS numbered
+        WHERE collection_item.id = numbered.id
+            AND collection_item.position <> numbered.position"#,
+        [collection.id.into()],
+    ))
+    .await?;
+
+    txn.commit().await?;
+    Ok(result.rows_affected > 0)
+}
@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use chrono::DateTime;
+use database::entity::generated_image::{
+    ActiveModel as GeneratedImageActiveModel, Column as GeneratedImageColumn,
+    Entity as GeneratedImage,
+};
+use database::entity::inspiration_image::{
+    ActiveModel as InspirationImageActiveModel, Column as InspirationImageColumn,
+    Entity as InspirationImage,
+};
+use database::entity::sea_orm_active_enums::GenerationStatus;
+use database::prompt_drift;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sea_orm::sea_query::ColumnType;
+use sea_orm::{
+    ActiveEnum, ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, Iden,
+    Iterable, ModelTrait, QueryFilter, QueryOrder, Set, TransactionTrait, Value,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value as Json};
+use storage::Storage;
+use tracing::{event, instrument, Level};
+
+pub const MANIFEST_VERSION: u32 = 1;
+const MANIFEST_PATH: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    pub version: u32,
+    pub exported_at: String,
+    pub pairs: Vec<ExportedPair>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportedPair {
+    pub inspiration_image: ExportedImage,
+    pub generated_images: Vec<ExportedImage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportedImage {
+    /// Every column of the row, keyed by column name.
+    #[serde(flatten)]
+    pub columns: Map<String, Json>,
+    /// Path of the image bytes inside the archive.
+    pub image_path: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub inspiration_images: usize,
+    pub generated_images: usize,
+}
+
+/// Loads an image from storage when we have a key for it, otherwise from its
+/// url. Images that can't be loaded are exported without bytes.
+async fn load_image(storage: &dyn Storage, key: Option<&str>, url: &str) -> Option<Vec<u8>> {
+    let result = match key {
+        Some(key) => storage.get(key).await,
+        None => {
+            async {
+                let res = reqwest::get(url).await?.error_for_status()?;
+                Ok::<_, anyhow::Error>(res.bytes().await?.to_vec())
+            }
+            .await
+        }
+    };
+
+    match result {
+        Ok(data) => Some(data),
+        Err(e) => {
+            event!(Level::WARN, "Skipping image bytes for {url}: {e}");
+            None
+        }
+    }
+}
+
+fn extension_for_mime_type(mime_type: Option<&str>) -> &'static str {
+    match mime_type {
+        Some("image/jpeg") => "jpg",
+        Some("image/png") => "png",
+        Some("image/webp") => "webp",
+        _ => "bin",
+    }
+}
+
+fn append_file<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    data: &[u8],
+) -> anyhow::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp() as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+fn value_to_json(value: Value) -> anyhow::Result<Json> {
+    Ok(match value {
+        Value::Bool(value) => json!(value),
+        Value::Int(value) => json!(value),
+        Value::BigInt(value) => json!(value),
+        Value::Float(value) => json!(value),
+        Value::Double(value) => json!(value),
+        Value::String(value) => json!(value),
+        Value::Json(value) => value.map_or(Json::Null, |value| *value),
+        Value::ChronoDateTimeWithTimeZone(value) => json!(value.map(|value| value.to_rfc3339())),
+        value => return Err(anyhow!("Unsupported value {value:?}")),
+    })
+}
+
+fn json_to_value(column_type: &ColumnType, json: Json) -> anyhow::Result<Value> {
+    Ok(match column_type {
+        ColumnType::String(_)
+        | ColumnType::Char(_)
+        | ColumnType::Text
+        | ColumnType::Enum { .. } => serde_json::from_value::<Option<String>>(json)?.into(),
+        ColumnType::Integer => serde_json::from_value::<Option<i32>>(json)?.into(),
+        ColumnType::BigInteger => serde_json::from_value::<Option<i64>>(json)?.into(),
+        ColumnType::Float => serde_json::from_value::<Option<f32>>(json)?.into(),
+        ColumnType::Double => serde_json::from_value::<Option<f64>>(json)?.into(),
+        ColumnType::Boolean => serde_json::from_value::<Option<bool>>(json)?.into(),
+        ColumnType::Json | ColumnType::JsonBinary => {
+            serde_json::from_value::<Option<Json>>(json)?.into()
+        }
+        ColumnType::TimestampWithTimeZone => serde_json::from_value::<Option<String>>(json)?
+            .map(|value| DateTime::parse_from_rfc3339(&value))
+            .transpose()?
+            .into(),
+        column_type => return Err(anyhow!("Unsupported column type {column_type:?}")),
+    })
+}
+
+/// Reads every column of `model`, so new columns are exported without
+/// listing them here.
+fn export_columns<M: ModelTrait>(model: &M) -> anyhow::Result<Map<String, Json>> {
+    <M::Entity as EntityTrait>::Column::iter()
+        .map(|column| Ok((column.to_string(), value_to_json(model.get(column))?)))
+        .collect()
+}
+
+/// Sets the columns present in `columns`, apart from those in `skip`. Values
+/// are checked against the column first, as `set` panics on a mismatch.
+fn import_columns<A: ActiveModelTrait>(
+    active_model: &mut A,
+    columns: &Map<String, Json>,
+    skip: &[<A::Entity as EntityTrait>::Column],
+) -> anyhow::Result<()> {
+    let skip: Vec<String> = skip.iter().map(|column| column.to_string()).collect();
+    for column in <A::Entity as EntityTrait>::Column::iter() {
+        let name = column.to_string();
+        let Some(json) = columns.get(&name).filter(|_| !skip.contains(&name)) else {
+            continue;
+        };
+        let def = column.def();
+        if json.is_null() && !def.is_null() {
+            return Err(anyhow!("Column {name} can't be null"));
+        }
+        let value = json_to_value(def.get_column_type(), json.clone())
+            .map_err(|e| anyhow!("Invalid value for {name}: {e}"))?;
+        active_model.set(column, value);
+    }
+    Ok(())
+}
+
+/// Writes every inspiration image, its generated images and their bytes to a
+/// gzipped tarball with a JSON manifest at `manifest.json`.
+#[instrument(skip(storage))]
+pub async fn export_dataset(
+    db: &DatabaseConnection,
+    storage: Arc<dyn Storage>,
+    path: &Path,
+) -> anyhow::Result<Manifest> {
+    let file = File::create(path)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let inspiration_images = InspirationImage::find()
+        .order_by_asc(InspirationImageColumn::Id)
+        .all(db)
+        .await?;
+
+    let mut pairs = Vec::new();
+    for inspiration_image in inspiration_images {
+        let data = load_image(
+            storage.as_ref(),
+            inspiration_image.archived_key.as_deref(),
+            &inspiration_image.source_url,
+        )
+        .await;
+        let image_path = match data {
+            Some(data) => {
+                let image_path = format!(
+                    "images/inspiration/{}.{}",
+                    inspiration_image.id,
+                    extension_for_mime_type(inspiration_image.mime_type.as_deref())
+                );
+                append_file(&mut builder, &image_path, &data)?;
+                Some(image_path)
+            }
+            None => None,
+        };
+
+        let generated = GeneratedImage::find()
+            .filter(GeneratedImageColumn::InspirationImageId.eq(inspiration_image.id))
+            .order_by_asc(GeneratedImageColumn::Id)
+            .all(db)
+            .await?;
+        let mut generated_images = Vec::new();
+        for generated_image in generated {
+            let data = load_image(
+                storage.as_ref(),
+                generated_image.storage_key.as_deref(),
+                &generated_image.source_url,
+            )
+            .await;
+            let image_path = match data {
+                Some(data) => {
+                    let image_path = format!("images/generated/{}.png", generated_image.id);
+                    append_file(&mut builder, &image_path, &data)?;
+                    Some(image_path)
+                }
+                None => None,
+            };
+
+            generated_images.push(ExportedImage {
+                columns: export_columns(&generated_image)?,
+                image_path,
+            });
+        }
+
+        pairs.push(ExportedPair {
+            inspiration_image: ExportedImage {
+                columns: export_columns(&inspiration_image)?,
+                image_path,
+            },
+            generated_images,
+        });
+    }
+
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        pairs,
+    };
+    append_file(
+        &mut builder,
+        MANIFEST_PATH,
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    builder.into_inner()?.finish()?;
+
+    event!(Level::INFO, "Exported {} pairs", manifest.pairs.len());
+    Ok(manifest)
+}
+
+fn open_archive(path: &Path) -> anyhow::Result<tar::Archive<GzDecoder<File>>> {
+    Ok(tar::Archive::new(GzDecoder::new(File::open(path)?)))
+}
+
+fn read_manifest(path: &Path) -> anyhow::Result<Manifest> {
+    let mut archive = open_archive(path)?;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.path()?.as_ref() == Path::new(MANIFEST_PATH) {
+            return Ok(serde_json::from_reader(entry)?);
+        }
+    }
+    Err(anyhow!("Archive has no {MANIFEST_PATH}"))
+}
+
+struct Upload {
+    key: String,
+    mime_type: String,
+}
+
+/// Streams the archive and writes the image at each path in `uploads` to its
+/// key, pushing the keys written to `written`.
+async fn write_images(
+    storage: &dyn Storage,
+    path: &Path,
+    mut uploads: HashMap<String, Upload>,
+    written: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    let mut archive = open_archive(path)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().to_string();
+        let Some(upload) = uploads.remove(&entry_path) else {
+            continue;
+        };
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        storage.put(&upload.key, data, &upload.mime_type).await?;
+        written.push(upload.key);
+    }
+
+    match uploads.into_keys().next() {
+        Some(image_path) => Err(anyhow!("Archive has no {image_path}")),
+        None => Ok(()),
+    }
+}
+
+/// Restores an archive written by `export_dataset`. Rows are inserted in a
+/// single transaction with new ids. Image bytes are written to `storage`
+/// before it commits, and removed again if the import fails.
+#[instrument(skip(storage))]
+pub async fn import_dataset(
+    db: &DatabaseConnection,
+    storage: Arc<dyn Storage>,
+    path: &Path,
+) -> anyhow::Result<ImportSummary> {
+    let manifest = read_manifest(path)?;
+    if manifest.version != MANIFEST_VERSION {
+        return Err(anyhow!("Unsupported manifest version {}", manifest.version));
+    }
+
+    let mut summary = ImportSummary::default();
+    let mut uploads = HashMap::new();
+    let txn = db.begin().await?;
+    for pair in manifest.pairs {
+        let exported = pair.inspiration_image;
+        if let Some(Json::String(status)) = exported
+            .columns
+            .get(&InspirationImageColumn::GenerationStatus.to_string())
+        {
+            GenerationStatus::try_from_value(status)?;
+        }
+        let mut inspiration_image: InspirationImageActiveModel = Default::default();
+        import_columns(
+            &mut inspiration_image,
+            &exported.columns,
+            &[
+                InspirationImageColumn::Id,
+                InspirationImageColumn::ArchivedKey,
+                InspirationImageColumn::ArchivedUrl,
+            ],
+        )?;
+        let mut inspiration_image = inspiration_image.insert(&txn).await?;
+
+        if let Some(image_path) = exported.image_path {
+            let key = format!(
+                "inspiration/{}.{}",
+                inspiration_image.source_id,
+                extension_for_mime_type(inspiration_image.mime_type.as_deref())
+            );
+            let mime_type = inspiration_image
+                .mime_type
+                .clone()
+                .unwrap_or("application/octet-stream".to_string());
+
+            let mut archived: InspirationImageActiveModel = inspiration_image.into();
+            archived.archived_url = Set(Some(storage.url(&key)));
+            archived.archived_key = Set(Some(key.clone()));
+            inspiration_image = archived.update(&txn).await?;
+            uploads.insert(image_path, Upload { key, mime_type });
+        }
+        summary.inspiration_images += 1;
+
+        for exported in pair.generated_images {
+            let mut generated_image: GeneratedImageActiveModel = Default::default();
+            import_columns(
+                &mut generated_image,
+                &exported.columns,
+                &[
+                    GeneratedImageColumn::Id,
+                    GeneratedImageColumn::InspirationImageId,
+                    GeneratedImageColumn::StorageKey,
+                    GeneratedImageColumn::IdempotencyKey,
+                    GeneratedImageColumn::PromptSimilarity,
+                    GeneratedImageColumn::PromptLengthRatio,
+                    GeneratedImageColumn::AddedConcepts,
+                    GeneratedImageColumn::RemovedConcepts,
+                ],
+            )?;
+            generated_image.inspiration_image_id = Set(inspiration_image.id);
+
+            if let Some(image_path) = exported.image_path {
+                let key = format!(
+                    "generated/{}-{}.png",
+                    inspiration_image.id,
+                    uuid::Uuid::new_v4()
+                );
+                generated_image.source_url = Set(storage.url(&key));
+                generated_image.storage_key = Set(Some(key.clone()));
+                uploads.insert(
+                    image_path,
+                    Upload {
+                        key,
+                        mime_type: "image/png".to_string(),
+                    },
+                );
+            }
+
+            let generation_source = match &generated_image.generation_source {
+                ActiveValue::Set(source) | ActiveValue::Unchanged(source) => source.clone(),
+                ActiveValue::NotSet => None,
+            };
+            let drift = match (&generated_image.prompt, &generated_image.revised_prompt) {
+                (ActiveValue::Set(prompt), ActiveValue::Set(revised_prompt))
+                    if prompt_drift::is_prompted(revised_prompt, generation_source.as_deref()) =>
+                {
+                    Some(prompt_drift::analyze(prompt, revised_prompt))
+                }
+                _ => None,
+            };
+            if let Some(drift) = drift {
+                drift.apply_to(&mut generated_image);
+            }
+            generated_image.insert(&txn).await?;
+            summary.generated_images += 1;
+        }
+    }
+
+    let mut written = Vec::new();
+    let result = match write_images(storage.as_ref(), path, uploads, &mut written).await {
+        Ok(()) => txn.commit().await.map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        for key in written {
+            if let Err(e) = storage.delete(&key).await {
+                event!(Level::WARN, "Failed to remove imported image {key}: {e}");
+            }
+        }
+        return Err(e);
+    }
+
+    event!(
+        Level::INFO,
+        "Imported {} inspiration images and {} generated images",
+        summary.inspiration_images,
+        summary.generated_images
+    );
+    Ok(summary)
+}
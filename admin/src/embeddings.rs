@@ -0,0 +1,35 @@
+use database::embedding::missing_embeddings;
+use database::entity::generated_image::Entity as GeneratedImage;
+use database::entity::inspiration_image::Entity as InspirationImage;
+use image_generator::embeddings::embed_generated_image;
+use image_generator::open_ai::OpenAiClient;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use tracing::{event, instrument, Level};
+
+/// Embeds every generated image that doesn't have an embedding yet. Failures
+/// are logged and skipped so one bad row doesn't stop the backfill.
+#[instrument(skip(db, client))]
+pub async fn backfill_embeddings(
+    db: &DatabaseConnection,
+    client: &OpenAiClient,
+) -> anyhow::Result<u64> {
+    let mut embedded = 0;
+    for id in missing_embeddings(db).await? {
+        let Some(generated_image) = GeneratedImage::find_by_id(id).one(db).await? else {
+            continue;
+        };
+        let description = InspirationImage::find_by_id(generated_image.inspiration_image_id)
+            .one(db)
+            .await?
+            .and_then(|image| image.description);
+
+        match embed_generated_image(db, client, &generated_image, description.as_deref()).await {
+            Ok(true) => embedded += 1,
+            Ok(false) => {}
+            Err(e) => event!(Level::WARN, "Error embedding generated image {id}: {e}"),
+        }
+    }
+
+    event!(Level::INFO, "Embedded {embedded} generated images");
+    Ok(embedded)
+}
@@ -0,0 +1,7 @@
+pub mod collections;
+pub mod dataset;
+pub mod embeddings;
+pub mod prompt_drift;
+pub mod regenerate;
+pub mod seed;
+pub mod tags;
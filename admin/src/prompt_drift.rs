@@ -0,0 +1,42 @@
+use database::entity::generated_image::{
+    ActiveModel as GeneratedImageActiveModel, Column as GeneratedImageColumn,
+    Entity as GeneratedImage,
+};
+use database::prompt_drift;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+};
+use tracing::{event, instrument, Level};
+
+/// Computes prompt drift for generated images saved before the analysis
+/// existed. With `all` set every prompted row is recomputed.
+#[instrument(skip(db))]
+pub async fn backfill_prompt_drift(db: &DatabaseConnection, all: bool) -> anyhow::Result<u64> {
+    let mut query = GeneratedImage::find().order_by_asc(GeneratedImageColumn::Id);
+    if !all {
+        query = query.filter(GeneratedImageColumn::PromptSimilarity.is_null());
+    }
+
+    let mut updated = 0;
+    for generated_image in query.all(db).await? {
+        // Variations and images without a revised prompt have nothing to
+        // compare.
+        if !prompt_drift::is_prompted(
+            &generated_image.revised_prompt,
+            generated_image.generation_source.as_deref(),
+        ) {
+            continue;
+        }
+        let drift = prompt_drift::analyze(&generated_image.prompt, &generated_image.revised_prompt);
+        let mut model: GeneratedImageActiveModel = generated_image.into();
+        drift.apply_to(&mut model);
+        model.update(db).await?;
+        updated += 1;
+    }
+
+    event!(
+        Level::INFO,
+        "Analyzed prompt drift for {updated} generated images"
+    );
+    Ok(updated)
+}
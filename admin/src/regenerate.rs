@@ -0,0 +1,60 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use database::entity::inspiration_image::{
+    Column as InspirationImageColumn, Entity as InspirationImage,
+};
+use database::messages::{GenerateImageEnvelope, GenerationParameters};
+use database::outbox::enqueue_at;
+use database::{GenerateImageMessage, Lane};
+use image_generator::messages::validate_parameters;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect, TransactionTrait,
+};
+
+/// Queues a new generation for each inspiration image on the priority lane,
+/// so they run ahead of the daily batch. Messages with a `deliver_at` stay
+/// invisible to the generator until then. The messages are written to the
+/// outbox, which the collector and the generator both relay to the queue.
+/// Parameters the backend doesn't support are an error, rather than a
+/// message the generator dead-letters.
+pub async fn request_regenerations(
+    db: &DatabaseConnection,
+    inspiration_image_ids: &[i32],
+    parameters: GenerationParameters,
+    deliver_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<Vec<GenerateImageEnvelope>> {
+    validate_parameters(&parameters).map_err(|e| anyhow!(e.to_string()))?;
+
+    let found: Vec<i32> = InspirationImage::find()
+        .select_only()
+        .column(InspirationImageColumn::Id)
+        .filter(InspirationImageColumn::Id.is_in(inspiration_image_ids.to_vec()))
+        .into_tuple()
+        .all(db)
+        .await?;
+    if let Some(missing) = inspiration_image_ids.iter().find(|id| !found.contains(id)) {
+        return Err(anyhow!("No inspiration image with id {missing}"));
+    }
+
+    let txn = db.begin().await?;
+    let mut envelopes = Vec::new();
+    for &inspiration_image_id in inspiration_image_ids {
+        let envelope = GenerateImageMessage {
+            request_id: Some(uuid::Uuid::new_v4().to_string()),
+            parameters: parameters.clone(),
+            ..GenerateImageMessage::new(inspiration_image_id)
+        }
+        .into_envelope();
+        enqueue_at(
+            &txn,
+            Lane::Priority.queue_name(),
+            &envelope,
+            deliver_at.map(Into::into),
+        )
+        .await?;
+        envelopes.push(envelope);
+    }
+    txn.commit().await?;
+
+    Ok(envelopes)
+}
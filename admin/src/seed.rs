@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use database::entity::inspiration_image::{
+    Column as InspirationImageColumn, Entity as InspirationImage,
+};
+use database::testing::fixtures::{synthetic_tags, PairFixture, SeededPair, SEED_SOURCE_PREFIX};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect};
+use storage::Storage;
+use tracing::instrument;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeedOptions {
+    pub pairs: u32,
+    pub tags: bool,
+}
+
+/// The index after the highest seeded so far. Seeded pairs can be deleted,
+/// so counting them could reuse an index that is still taken.
+async fn next_seed_index(db: &DatabaseConnection) -> anyhow::Result<u32> {
+    let source_ids: Vec<String> = InspirationImage::find()
+        .select_only()
+        .column(InspirationImageColumn::SourceId)
+        .filter(InspirationImageColumn::SourceId.starts_with(SEED_SOURCE_PREFIX))
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    Ok(source_ids
+        .iter()
+        .filter_map(|source_id| source_id[SEED_SOURCE_PREFIX.len()..].parse::<u32>().ok())
+        .max()
+        .map_or(0, |index| index + 1))
+}
+
+/// Adds `options.pairs` synthetic pairs after any seeded earlier.
+#[instrument(skip(storage))]
+pub async fn seed(
+    db: &DatabaseConnection,
+    storage: Arc<dyn Storage>,
+    options: SeedOptions,
+) -> anyhow::Result<Vec<SeededPair>> {
+    let first = next_seed_index(db).await?;
+
+    let mut seeded = Vec::new();
+    for index in first..first + options.pairs {
+        let mut fixture = PairFixture::new(index);
+        if options.tags {
+            fixture = fixture.tags(synthetic_tags(index));
+        }
+        seeded.push(fixture.insert(db, storage.clone()).await?);
+    }
+    Ok(seeded)
+}
@@ -0,0 +1,32 @@
+use database::entity::generated_image::{Column as GeneratedImageColumn, Entity as GeneratedImage};
+use database::tags::tag_from_prompt;
+use sea_orm::{DatabaseConnection, EntityTrait, QueryOrder};
+use tracing::{event, instrument, Level};
+
+/// Re-runs prompt tag extraction over every generated image, e.g. after
+/// adding tags that older prompts mention. Returns the number of images that
+/// matched at least one tag.
+#[instrument(skip(db))]
+pub async fn tag_all_from_prompts(db: &DatabaseConnection) -> anyhow::Result<u64> {
+    let generated_images = GeneratedImage::find()
+        .order_by_asc(GeneratedImageColumn::Id)
+        .all(db)
+        .await?;
+
+    let mut tagged = 0;
+    for generated_image in generated_images {
+        let tags = tag_from_prompt(
+            db,
+            generated_image.id,
+            &generated_image.prompt,
+            &generated_image.revised_prompt,
+        )
+        .await?;
+        if !tags.is_empty() {
+            tagged += 1;
+        }
+    }
+
+    event!(Level::INFO, "Tagged {tagged} generated images from prompts");
+    Ok(tagged)
+}
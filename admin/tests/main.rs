@@ -0,0 +1,353 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use admin::collections::{add_to_collection, create_collection, remove_from_collection};
+    use admin::dataset::{export_dataset, import_dataset, ImportSummary, MANIFEST_VERSION};
+    use admin::regenerate::request_regenerations;
+    use admin::seed::{seed, SeedOptions};
+    use database::entity::collection_item::{
+        Column as CollectionItemColumn, Entity as CollectionItem,
+    };
+    use database::entity::generated_image::{
+        ActiveModel as GeneratedImageActiveModel, Entity as GeneratedImage,
+    };
+    use database::entity::inspiration_image::{
+        ActiveModel as InspirationImageActiveModel, Entity as InspirationImage,
+    };
+    use database::entity::sea_orm_active_enums::ImageKind;
+    use database::messages::GenerationParameters;
+    use database::tags::tags_for_image;
+    use database::testing::fixtures::{placeholder_image, PLACEHOLDER_SIZE};
+    use migration::testing::migrated_database;
+    use sea_orm::{ActiveModelTrait, DatabaseConnection, EntityTrait, QueryOrder, Set};
+    use storage::{LocalStorage, Storage};
+
+    #[tokio::test]
+    async fn test_export_and_import_round_trip() {
+        let source_database = migrated_database().await;
+        let target_database = migrated_database().await;
+        let source_db = &source_database.db;
+        let target_db = &target_database.db;
+
+        let root = std::env::temp_dir().join(format!("admin-test-{}", std::process::id()));
+        let source_storage = Arc::new(LocalStorage::new(root.join("source"), "/files".to_string()));
+        let target_storage = Arc::new(LocalStorage::new(root.join("target"), "/files".to_string()));
+
+        source_storage
+            .put("inspiration/abc.jpg", b"inspiration".to_vec(), "image/jpeg")
+            .await
+            .unwrap();
+        source_storage
+            .put("1_generated", b"generated".to_vec(), "image/png")
+            .await
+            .unwrap();
+        let inspiration_image = InspirationImageActiveModel {
+            source_id: Set("abc".to_string()),
+            source_url: Set("https://example.com/abc.jpg".to_string()),
+            description: Set(Some("a dog on a beach".to_string())),
+            archived_key: Set(Some("inspiration/abc.jpg".to_string())),
+            mime_type: Set(Some("image/jpeg".to_string())),
+            width: Set(Some(640)),
+            ..Default::default()
+        }
+        .insert(source_db)
+        .await
+        .unwrap();
+        GeneratedImageActiveModel {
+            inspiration_image_id: Set(inspiration_image.id),
+            source_url: Set("/files/1_generated".to_string()),
+            storage_key: Set(Some("1_generated".to_string())),
+            prompt: Set("a dog on a beach".to_string()),
+            revised_prompt: Set("A golden retriever on a sandy beach".to_string()),
+            size: Set(Some("1024x1024".to_string())),
+            ..Default::default()
+        }
+        .insert(source_db)
+        .await
+        .unwrap();
+
+        let archive_path = root.join("export.tar.gz");
+        let manifest = export_dataset(source_db, source_storage, &archive_path)
+            .await
+            .unwrap();
+        assert_eq!(manifest.pairs.len(), 1);
+
+        let summary = import_dataset(target_db, target_storage.clone(), &archive_path)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                inspiration_images: 1,
+                generated_images: 1
+            }
+        );
+
+        let imported_inspiration = InspirationImage::find()
+            .one(target_db)
+            .await
+            .unwrap()
+            .unwrap();
+        let imported_generated = GeneratedImage::find()
+            .one(target_db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(imported_inspiration.source_id, "abc");
+        assert_eq!(imported_inspiration.width, Some(640));
+        assert_eq!(
+            imported_generated.revised_prompt,
+            "A golden retriever on a sandy beach"
+        );
+        assert_eq!(imported_generated.size.as_deref(), Some("1024x1024"));
+        assert!(imported_generated.prompt_similarity.is_some());
+        assert_eq!(
+            target_storage
+                .get(&imported_inspiration.archived_key.unwrap())
+                .await
+                .unwrap(),
+            b"inspiration".to_vec()
+        );
+        assert_eq!(
+            target_storage
+                .get(&imported_generated.storage_key.unwrap())
+                .await
+                .unwrap(),
+            b"generated".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_import_removes_written_images() {
+        let database = migrated_database().await;
+        let db = &database.db;
+        let root = std::env::temp_dir().join(format!("import-test-{}", std::process::id()));
+        let storage = Arc::new(LocalStorage::new(
+            root.join("storage"),
+            "/files".to_string(),
+        ));
+
+        // The manifest lists a generated image the archive doesn't contain.
+        let manifest = serde_json::json!({
+            "version": MANIFEST_VERSION,
+            "exported_at": "2024-06-01T00:00:00Z",
+            "pairs": [{
+                "inspiration_image": {
+                    "source_id": "abc",
+                    "source_url": "https://example.com/abc.jpg",
+                    "mime_type": "image/jpeg",
+                    "image_path": "images/inspiration/1.jpg"
+                },
+                "generated_images": [{
+                    "source_url": "https://example.com/generated/1.png",
+                    "prompt": "a dog",
+                    "revised_prompt": "A dog",
+                    "image_path": "images/generated/1.png"
+                }]
+            }]
+        });
+        std::fs::create_dir_all(&root).unwrap();
+        let archive_path = root.join("broken.tar.gz");
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            std::fs::File::create(&archive_path).unwrap(),
+            flate2::Compression::default(),
+        ));
+        for (path, data) in [
+            ("images/inspiration/1.jpg", b"inspiration".to_vec()),
+            ("manifest.json", serde_json::to_vec(&manifest).unwrap()),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, data.as_slice())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        assert!(import_dataset(db, storage.clone(), &archive_path)
+            .await
+            .is_err());
+        assert!(InspirationImage::find().all(db).await.unwrap().is_empty());
+        assert!(!storage.exists("inspiration/abc.jpg").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_seed_adds_pairs_after_earlier_seeds() {
+        let database = migrated_database().await;
+        let db = &database.db;
+        let root = std::env::temp_dir().join(format!("seed-test-{}", std::process::id()));
+        let storage = Arc::new(LocalStorage::new(root, "/files".to_string()));
+
+        let first = seed(
+            db,
+            storage.clone(),
+            SeedOptions {
+                pairs: 3,
+                tags: true,
+            },
+        )
+        .await
+        .unwrap();
+        let second = seed(
+            db,
+            storage.clone(),
+            SeedOptions {
+                pairs: 2,
+                tags: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        let source_ids: Vec<&str> = first
+            .iter()
+            .chain(&second)
+            .map(|pair| pair.inspiration_image.source_id.as_str())
+            .collect();
+        assert_eq!(
+            source_ids,
+            vec!["seed-0", "seed-1", "seed-2", "seed-3", "seed-4"]
+        );
+        assert_eq!(GeneratedImage::find().all(db).await.unwrap().len(), 5);
+
+        let pair = &first[1];
+        assert_eq!(
+            pair.generated_image.inspiration_image_id,
+            pair.inspiration_image.id
+        );
+        assert_eq!(
+            storage.get("generated/seed-1.png").await.unwrap(),
+            placeholder_image(3, PLACEHOLDER_SIZE, PLACEHOLDER_SIZE).unwrap()
+        );
+        assert!(
+            !tags_for_image(db, ImageKind::Inspiration, pair.inspiration_image.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            tags_for_image(db, ImageKind::Inspiration, second[0].inspiration_image.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // Deleting an earlier pair doesn't make the next seed reuse an index.
+        GeneratedImage::delete_by_id(first[0].generated_image.id)
+            .exec(db)
+            .await
+            .unwrap();
+        InspirationImage::delete_by_id(first[0].inspiration_image.id)
+            .exec(db)
+            .await
+            .unwrap();
+        let third = seed(
+            db,
+            storage.clone(),
+            SeedOptions {
+                pairs: 1,
+                tags: false,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(third[0].inspiration_image.source_id, "seed-5");
+    }
+
+    #[tokio::test]
+    async fn test_removing_collection_items_keeps_positions_contiguous() {
+        let database = migrated_database().await;
+        let db = &database.db;
+        let root = std::env::temp_dir().join(format!("collection-test-{}", std::process::id()));
+        let storage = Arc::new(LocalStorage::new(root, "/files".to_string()));
+        let ids: Vec<i32> = seed(
+            db,
+            storage,
+            SeedOptions {
+                pairs: 4,
+                tags: false,
+            },
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|pair| pair.generated_image.id)
+        .collect();
+
+        create_collection(db, "favourites", "Favourites", None)
+            .await
+            .unwrap();
+        assert_eq!(add_to_collection(db, "favourites", &ids).await.unwrap(), 4);
+        assert!(remove_from_collection(db, "favourites", ids[1])
+            .await
+            .unwrap());
+        assert!(!remove_from_collection(db, "favourites", ids[1])
+            .await
+            .unwrap());
+
+        let items: Vec<(i32, i32)> = CollectionItem::find()
+            .order_by_asc(CollectionItemColumn::Position)
+            .all(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|item| (item.generated_image_id, item.position))
+            .collect();
+        assert_eq!(items, vec![(ids[0], 0), (ids[2], 1), (ids[3], 2)]);
+
+        add_to_collection(db, "favourites", &ids[1..2])
+            .await
+            .unwrap();
+        let last = CollectionItem::find()
+            .order_by_desc(CollectionItemColumn::Position)
+            .one(db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((last.generated_image_id, last.position), (ids[1], 3));
+    }
+
+    #[tokio::test]
+    async fn test_regenerate_rejects_unsupported_parameters() {
+        // Rejected before the database is read, so nothing is queued.
+        let db = DatabaseConnection::Disconnected;
+        for parameters in [
+            GenerationParameters {
+                size: Some("640x480".to_string()),
+                ..Default::default()
+            },
+            GenerationParameters {
+                quality: Some("ultra".to_string()),
+                ..Default::default()
+            },
+            GenerationParameters {
+                style: Some("noir".to_string()),
+                ..Default::default()
+            },
+            GenerationParameters {
+                source: Some("sketch".to_string()),
+                ..Default::default()
+            },
+        ] {
+            let error = request_regenerations(&db, &[1], parameters, None)
+                .await
+                .unwrap_err();
+            assert!(error.to_string().contains("unsupported"), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_placeholder_images_are_deterministic() {
+        assert_eq!(
+            placeholder_image(7, 16, 16).unwrap(),
+            placeholder_image(7, 16, 16).unwrap()
+        );
+        assert_ne!(
+            placeholder_image(7, 16, 16).unwrap(),
+            placeholder_image(8, 16, 16).unwrap()
+        );
+    }
+}
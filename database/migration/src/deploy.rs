@@ -0,0 +1,197 @@
+//! Running migrations safely when several instances start at once, and
+//! checking at startup that the schema is the one a service was built for.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{
+    Database, DatabaseConnection, DbBackend, ProxyDatabaseTrait, ProxyExecResult, ProxyRow,
+    Statement, TransactionTrait, Value,
+};
+
+use crate::Migrator;
+
+/// Key of the Postgres advisory lock held while migrating, so only one
+/// instance migrates at a time and the others wait and find nothing to do.
+pub const MIGRATION_LOCK_KEY: i64 = 7_242_019_031;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaStatus {
+    /// Migrations this build has that the database hasn't applied.
+    pub pending: Vec<String>,
+    /// Migrations the database has applied that this build doesn't know,
+    /// because a newer version has migrated it.
+    pub unknown: Vec<String>,
+}
+
+impl SchemaStatus {
+    pub fn is_current(&self) -> bool {
+        self.pending.is_empty() && self.unknown.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedMigration {
+    pub name: String,
+    /// Every statement the migration would run, including the queries it
+    /// uses to decide what to do.
+    pub statements: Vec<String>,
+}
+
+fn lock_statement() -> Statement {
+    Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT pg_advisory_xact_lock($1)",
+        [MIGRATION_LOCK_KEY.into()],
+    )
+}
+
+/// A change to the schema, run while holding the migration lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationCommand {
+    /// Applies pending migrations, up to the given number of them.
+    Up(Option<u32>),
+    /// Rolls back applied migrations, the given number of them or all.
+    Down(Option<u32>),
+    /// Drops every table and applies all migrations again.
+    Fresh,
+    /// Rolls back every migration and applies them again.
+    Refresh,
+    /// Rolls back every migration.
+    Reset,
+}
+
+/// Runs `command` while holding the migration lock. The lock is tied to the
+/// transaction the migrations run in, so it is released when they commit or
+/// fail.
+pub async fn run_locked(db: &DatabaseConnection, command: MigrationCommand) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+    txn.execute(lock_statement()).await?;
+    match command {
+        MigrationCommand::Up(steps) => Migrator::up(&txn, steps).await?,
+        MigrationCommand::Down(steps) => Migrator::down(&txn, steps).await?,
+        MigrationCommand::Fresh => Migrator::fresh(&txn).await?,
+        MigrationCommand::Refresh => Migrator::refresh(&txn).await?,
+        MigrationCommand::Reset => Migrator::reset(&txn).await?,
+    }
+    txn.commit().await
+}
+
+/// Applies pending migrations, up to `steps` of them, while holding the
+/// migration lock.
+pub async fn migrate(db: &DatabaseConnection, steps: Option<u32>) -> Result<(), DbErr> {
+    run_locked(db, MigrationCommand::Up(steps)).await
+}
+
+pub async fn schema_status(db: &DatabaseConnection) -> Result<SchemaStatus, DbErr> {
+    let applied: HashSet<String> = Migrator::get_migration_models(db)
+        .await?
+        .into_iter()
+        .map(|model| model.version)
+        .collect();
+    let known: Vec<String> = Migrator::migrations()
+        .iter()
+        .map(|migration| migration.name().to_string())
+        .collect();
+
+    let mut unknown: Vec<String> = applied
+        .iter()
+        .filter(|version| !known.contains(version))
+        .cloned()
+        .collect();
+    unknown.sort();
+    Ok(SchemaStatus {
+        pending: known
+            .into_iter()
+            .filter(|name| !applied.contains(name))
+            .collect(),
+        unknown,
+    })
+}
+
+/// Fails unless the database has applied exactly the migrations this build
+/// knows about. Services call it at startup so they don't run against a
+/// schema their entities don't match.
+pub async fn check_schema(db: &DatabaseConnection) -> Result<(), DbErr> {
+    let status = schema_status(db).await?;
+    if status.is_current() {
+        return Ok(());
+    }
+
+    let mut problems = Vec::new();
+    if !status.pending.is_empty() {
+        problems.push(format!(
+            "migrations not applied yet: {}",
+            status.pending.join(", ")
+        ));
+    }
+    if !status.unknown.is_empty() {
+        problems.push(format!(
+            "migrations applied by a newer version: {}",
+            status.unknown.join(", ")
+        ));
+    }
+    Err(DbErr::Custom(format!(
+        "Database schema doesn't match this build, {}",
+        problems.join("; ")
+    )))
+}
+
+/// Stands in for the database while planning: it records the statements a
+/// migration would run without running them. Queries get a single row back,
+/// as if whatever a migration checks for is there.
+#[derive(Debug, Clone, Default)]
+struct StatementRecorder(Arc<Mutex<Vec<String>>>);
+
+impl StatementRecorder {
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl ProxyDatabaseTrait for StatementRecorder {
+    fn query(&self, statement: Statement) -> Result<Vec<ProxyRow>, DbErr> {
+        self.0.lock().unwrap().push(statement.to_string());
+        Ok(vec![ProxyRow::new(BTreeMap::from([(
+            "?column?".to_string(),
+            Value::Int(Some(1)),
+        )]))])
+    }
+
+    fn execute(&self, statement: Statement) -> Result<ProxyExecResult, DbErr> {
+        self.0.lock().unwrap().push(statement.to_string());
+        Ok(ProxyExecResult::default())
+    }
+}
+
+/// Returns the SQL each pending migration would run. Only the list of
+/// applied migrations is read from `db`; the migrations themselves run
+/// against a [`StatementRecorder`], so nothing is executed.
+pub async fn plan(db: &DatabaseConnection) -> Result<Vec<PlannedMigration>, DbErr> {
+    let pending: HashSet<String> = Migrator::get_pending_migrations(db)
+        .await?
+        .iter()
+        .map(|migration| migration.name().to_string())
+        .collect();
+
+    let mut planned = Vec::new();
+    for migration in Migrator::migrations() {
+        if !pending.contains(migration.name()) {
+            continue;
+        }
+        let recorder = StatementRecorder::default();
+        let connection = Database::connect_proxy(
+            DbBackend::Postgres,
+            Arc::new(Mutex::new(Box::new(recorder.clone()))),
+        )
+        .await?;
+        migration.up(&SchemaManager::new(&connection)).await?;
+        planned.push(PlannedMigration {
+            name: migration.name().to_string(),
+            statements: recorder.take(),
+        });
+    }
+
+    Ok(planned)
+}
@@ -1,8 +1,34 @@
 pub use sea_orm_migration::prelude::*;
 
+mod deploy;
+pub use deploy::{
+    check_schema, migrate, plan, run_locked, schema_status, MigrationCommand, PlannedMigration,
+    SchemaStatus, MIGRATION_LOCK_KEY,
+};
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
 mod m20220101_000001_create_table;
 mod m20240301_053139_create_generated_image;
 mod m20240313_032654_add_descripiton_to_generated_image;
+mod m20240318_041522_add_generation_status_to_inspiration_image;
+mod m20240322_014210_create_image_derivative;
+mod m20240326_221845_add_archive_to_inspiration_image;
+mod m20240402_190318_add_storage_key_to_generated_image;
+mod m20240409_032140_add_search_vectors;
+mod m20240415_203347_add_prompt_drift_to_generated_image;
+mod m20240420_174512_create_image_embedding;
+mod m20240427_110934_create_tags_and_collections;
+mod m20240503_091522_create_outbox_message;
+mod m20240508_142207_add_idempotency_key_to_generated_image;
+mod m20240514_083016_create_reconciliation_run;
+mod m20240519_160244_add_deliver_at_to_outbox_message;
+mod m20240530_101522_create_generation_cost;
+mod m20240604_083512_add_dimensions_to_inspiration_image;
+mod m20240604_091047_add_generation_parameters_to_generated_image;
+mod m20240611_074219_add_generation_source_to_generated_image;
+mod m20240624_103318_add_caption_to_inspiration_image;
 
 pub struct Migrator;
 
@@ -13,6 +39,23 @@ impl MigratorTrait for Migrator {
             Box::new(m20220101_000001_create_table::Migration),
             Box::new(m20240301_053139_create_generated_image::Migration),
             Box::new(m20240313_032654_add_descripiton_to_generated_image::Migration),
+            Box::new(m20240318_041522_add_generation_status_to_inspiration_image::Migration),
+            Box::new(m20240322_014210_create_image_derivative::Migration),
+            Box::new(m20240326_221845_add_archive_to_inspiration_image::Migration),
+            Box::new(m20240402_190318_add_storage_key_to_generated_image::Migration),
+            Box::new(m20240409_032140_add_search_vectors::Migration),
+            Box::new(m20240415_203347_add_prompt_drift_to_generated_image::Migration),
+            Box::new(m20240420_174512_create_image_embedding::Migration),
+            Box::new(m20240427_110934_create_tags_and_collections::Migration),
+            Box::new(m20240503_091522_create_outbox_message::Migration),
+            Box::new(m20240508_142207_add_idempotency_key_to_generated_image::Migration),
+            Box::new(m20240514_083016_create_reconciliation_run::Migration),
+            Box::new(m20240519_160244_add_deliver_at_to_outbox_message::Migration),
+            Box::new(m20240530_101522_create_generation_cost::Migration),
+            Box::new(m20240604_083512_add_dimensions_to_inspiration_image::Migration),
+            Box::new(m20240604_091047_add_generation_parameters_to_generated_image::Migration),
+            Box::new(m20240611_074219_add_generation_source_to_generated_image::Migration),
+            Box::new(m20240624_103318_add_caption_to_inspiration_image::Migration),
         ]
     }
 }
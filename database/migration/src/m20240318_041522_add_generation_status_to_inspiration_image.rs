@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(InspirationImage::Table)
+                    .add_column(ColumnDef::new(InspirationImage::GenerationStatus).string())
+                    .add_column(ColumnDef::new(InspirationImage::GenerationError).string())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(InspirationImage::Table)
+                    .drop_column(InspirationImage::GenerationStatus)
+                    .drop_column(InspirationImage::GenerationError)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum InspirationImage {
+    Table,
+    GenerationStatus,
+    GenerationError,
+}
@@ -0,0 +1,78 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImageDerivative::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImageDerivative::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ImageDerivative::ImageKind)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImageDerivative::ImageId).integer().not_null())
+                    .col(ColumnDef::new(ImageDerivative::Format).string().not_null())
+                    .col(ColumnDef::new(ImageDerivative::MimeType).string().not_null())
+                    .col(ColumnDef::new(ImageDerivative::Width).integer().not_null())
+                    .col(ColumnDef::new(ImageDerivative::Height).integer().not_null())
+                    .col(
+                        ColumnDef::new(ImageDerivative::StorageKey)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImageDerivative::Url).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // One row per variant, so redelivered post-processing replaces a
+        // derivative instead of adding another. It also serves lookups by
+        // image.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_image_derivative_variant")
+                    .table(ImageDerivative::Table)
+                    .col(ImageDerivative::ImageKind)
+                    .col(ImageDerivative::ImageId)
+                    .col(ImageDerivative::Width)
+                    .col(ImageDerivative::Format)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ImageDerivative::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ImageDerivative {
+    Table,
+    Id,
+    ImageKind,
+    ImageId,
+    Format,
+    MimeType,
+    Width,
+    Height,
+    StorageKey,
+    Url,
+}
@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(InspirationImage::Table)
+                    .add_column(ColumnDef::new(InspirationImage::ArchivedKey).string())
+                    .add_column(ColumnDef::new(InspirationImage::ArchivedUrl).string())
+                    .add_column(ColumnDef::new(InspirationImage::ContentHash).string())
+                    .add_column(ColumnDef::new(InspirationImage::MimeType).string())
+                    .add_column(ColumnDef::new(InspirationImage::PhotographerName).string())
+                    .add_column(ColumnDef::new(InspirationImage::PhotographerUrl).string())
+                    .add_column(ColumnDef::new(InspirationImage::UpstreamAvailable).boolean())
+                    .add_column(
+                        ColumnDef::new(InspirationImage::UpstreamCheckedAt)
+                            .timestamp_with_time_zone(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(InspirationImage::Table)
+                    .drop_column(InspirationImage::ArchivedKey)
+                    .drop_column(InspirationImage::ArchivedUrl)
+                    .drop_column(InspirationImage::ContentHash)
+                    .drop_column(InspirationImage::MimeType)
+                    .drop_column(InspirationImage::PhotographerName)
+                    .drop_column(InspirationImage::PhotographerUrl)
+                    .drop_column(InspirationImage::UpstreamAvailable)
+                    .drop_column(InspirationImage::UpstreamCheckedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum InspirationImage {
+    Table,
+    ArchivedKey,
+    ArchivedUrl,
+    ContentHash,
+    MimeType,
+    PhotographerName,
+    PhotographerUrl,
+    UpstreamAvailable,
+    UpstreamCheckedAt,
+}
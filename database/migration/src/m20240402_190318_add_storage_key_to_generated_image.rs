@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(GeneratedImage::Table)
+                    .add_column(ColumnDef::new(GeneratedImage::StorageKey).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Images generated before storage keys were recorded were uploaded to
+        // S3 as `{image_id}_{uuid}`, and their URL is the only place the key
+        // was kept.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE generated_image
+                SET storage_key = substring(source_url FROM '^https://[^/]+\.s3\.amazonaws\.com/(.+)$')
+                WHERE source_url ~ '^https://[^/]+\.s3\.amazonaws\.com/.+$'"#,
+            )
+            .await
+            .map(|_| ())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(GeneratedImage::Table)
+                    .drop_column(GeneratedImage::StorageKey)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum GeneratedImage {
+    Table,
+    StorageKey,
+}
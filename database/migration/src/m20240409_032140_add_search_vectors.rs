@@ -0,0 +1,52 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared(
+            r#"ALTER TABLE inspiration_image ADD COLUMN search_vector tsvector
+                GENERATED ALWAYS AS (to_tsvector('english', coalesce(description, ''))) STORED"#,
+        )
+        .await?;
+        db.execute_unprepared(
+            r#"ALTER TABLE generated_image ADD COLUMN search_vector tsvector
+                GENERATED ALWAYS AS (
+                    setweight(to_tsvector('english', coalesce(prompt, '')), 'A') ||
+                    setweight(to_tsvector('english', coalesce(revised_prompt, '')), 'B')
+                ) STORED"#,
+        )
+        .await?;
+        db.execute_unprepared(
+            r#"CREATE INDEX idx_inspiration_image_search_vector
+                ON inspiration_image USING GIN (search_vector)"#,
+        )
+        .await?;
+        db.execute_unprepared(
+            r#"CREATE INDEX idx_generated_image_search_vector
+                ON generated_image USING GIN (search_vector)"#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared("DROP INDEX IF EXISTS idx_generated_image_search_vector")
+            .await?;
+        db.execute_unprepared("DROP INDEX IF EXISTS idx_inspiration_image_search_vector")
+            .await?;
+        db.execute_unprepared("ALTER TABLE generated_image DROP COLUMN search_vector")
+            .await?;
+        db.execute_unprepared("ALTER TABLE inspiration_image DROP COLUMN search_vector")
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(GeneratedImage::Table)
+                    .add_column(ColumnDef::new(GeneratedImage::PromptSimilarity).double())
+                    .add_column(ColumnDef::new(GeneratedImage::PromptLengthRatio).double())
+                    .add_column(ColumnDef::new(GeneratedImage::AddedConcepts).json_binary())
+                    .add_column(ColumnDef::new(GeneratedImage::RemovedConcepts).json_binary())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(GeneratedImage::Table)
+                    .drop_column(GeneratedImage::PromptSimilarity)
+                    .drop_column(GeneratedImage::PromptLengthRatio)
+                    .drop_column(GeneratedImage::AddedConcepts)
+                    .drop_column(GeneratedImage::RemovedConcepts)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum GeneratedImage {
+    Table,
+    PromptSimilarity,
+    PromptLengthRatio,
+    AddedConcepts,
+    RemovedConcepts,
+}
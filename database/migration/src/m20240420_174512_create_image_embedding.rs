@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{DbBackend, Statement};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Failing here, rather than skipping the table, keeps the migration
+        // from being recorded as applied on a database that can't have it.
+        let available = db
+            .query_one(Statement::from_string(
+                DbBackend::Postgres,
+                "SELECT 1 FROM pg_available_extensions WHERE name = 'vector'".to_string(),
+            ))
+            .await?
+            .is_some();
+        if !available {
+            return Err(DbErr::Migration(
+                "The vector extension isn't available. Use a Postgres image that ships \
+                pgvector, such as pgvector/pgvector:pg16"
+                    .to_string(),
+            ));
+        }
+
+        db.execute_unprepared("CREATE EXTENSION IF NOT EXISTS vector")
+            .await?;
+        db.execute_unprepared(
+            r#"CREATE TABLE image_embedding (
+                generated_image_id integer PRIMARY KEY
+                    REFERENCES generated_image (id) ON DELETE CASCADE,
+                model varchar NOT NULL,
+                embedding vector(1536) NOT NULL,
+                created_at timestamptz NOT NULL DEFAULT now()
+            )"#,
+        )
+        .await?;
+        db.execute_unprepared(
+            r#"CREATE INDEX idx_image_embedding_embedding
+                ON image_embedding USING hnsw (embedding vector_cosine_ops)"#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared("DROP TABLE IF EXISTS image_embedding")
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Tag::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Tag::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Tag::Name).string().not_null().unique_key())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImageTag::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImageTag::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ImageTag::TagId).integer().not_null())
+                    .col(ColumnDef::new(ImageTag::ImageKind).string().not_null())
+                    .col(ColumnDef::new(ImageTag::ImageId).integer().not_null())
+                    .col(ColumnDef::new(ImageTag::Source).string().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_image_tag_tag")
+                            .from(ImageTag::Table, ImageTag::TagId)
+                            .to(Tag::Table, Tag::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_image_tag_unique")
+                    .table(ImageTag::Table)
+                    .col(ImageTag::TagId)
+                    .col(ImageTag::ImageKind)
+                    .col(ImageTag::ImageId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_image_tag_image")
+                    .table(ImageTag::Table)
+                    .col(ImageTag::ImageKind)
+                    .col(ImageTag::ImageId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Collection::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Collection::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Collection::Slug)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Collection::Name).string().not_null())
+                    .col(ColumnDef::new(Collection::Description).string())
+                    .col(
+                        ColumnDef::new(Collection::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(CollectionItem::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CollectionItem::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(CollectionItem::CollectionId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CollectionItem::GeneratedImageId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CollectionItem::Position)
+                            .integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_collection_item_collection")
+                            .from(CollectionItem::Table, CollectionItem::CollectionId)
+                            .to(Collection::Table, Collection::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_collection_item_generated_image")
+                            .from(CollectionItem::Table, CollectionItem::GeneratedImageId)
+                            .to(GeneratedImage::Table, GeneratedImage::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_collection_item_unique")
+                    .table(CollectionItem::Table)
+                    .col(CollectionItem::CollectionId)
+                    .col(CollectionItem::GeneratedImageId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CollectionItem::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Collection::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ImageTag::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Tag::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Tag {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(DeriveIden)]
+pub enum ImageTag {
+    Table,
+    Id,
+    TagId,
+    ImageKind,
+    ImageId,
+    Source,
+}
+
+#[derive(DeriveIden)]
+pub enum Collection {
+    Table,
+    Id,
+    Slug,
+    Name,
+    Description,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum CollectionItem {
+    Table,
+    Id,
+    CollectionId,
+    GeneratedImageId,
+    Position,
+}
+
+#[derive(DeriveIden)]
+pub enum GeneratedImage {
+    Table,
+    Id,
+}
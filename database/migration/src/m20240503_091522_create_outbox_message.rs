@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OutboxMessage::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OutboxMessage::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(OutboxMessage::QueueName).string().not_null())
+                    .col(
+                        ColumnDef::new(OutboxMessage::Payload)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OutboxMessage::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(OutboxMessage::SentAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(OutboxMessage::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(OutboxMessage::LastError).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        // The relay only ever looks at unsent rows.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"CREATE INDEX idx_outbox_message_unsent
+                    ON outbox_message (id) WHERE sent_at IS NULL"#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OutboxMessage::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum OutboxMessage {
+    Table,
+    Id,
+    QueueName,
+    Payload,
+    CreatedAt,
+    SentAt,
+    Attempts,
+    LastError,
+}
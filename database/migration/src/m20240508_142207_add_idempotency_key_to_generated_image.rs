@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(GeneratedImage::Table)
+                    .add_column(ColumnDef::new(GeneratedImage::IdempotencyKey).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Images generated before the key existed keep a null key, which the
+        // unique index allows any number of.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_generated_image_idempotency_key")
+                    .table(GeneratedImage::Table)
+                    .col(GeneratedImage::IdempotencyKey)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_generated_image_idempotency_key")
+                    .table(GeneratedImage::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(GeneratedImage::Table)
+                    .drop_column(GeneratedImage::IdempotencyKey)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum GeneratedImage {
+    Table,
+    IdempotencyKey,
+}
@@ -0,0 +1,77 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(GenerationCost::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GenerationCost::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(GenerationCost::InspirationImageId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(GenerationCost::IdempotencyKey).string())
+                    .col(ColumnDef::new(GenerationCost::Backend).string().not_null())
+                    .col(ColumnDef::new(GenerationCost::Model).string().not_null())
+                    .col(ColumnDef::new(GenerationCost::Size).string().not_null())
+                    .col(ColumnDef::new(GenerationCost::Quality).string().not_null())
+                    .col(
+                        ColumnDef::new(GenerationCost::CostMicros)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GenerationCost::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Budgets sum the spend since the start of the day or month.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_generation_cost_created_at")
+                    .table(GenerationCost::Table)
+                    .col(GenerationCost::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(GenerationCost::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum GenerationCost {
+    Table,
+    Id,
+    InspirationImageId,
+    IdempotencyKey,
+    Backend,
+    Model,
+    Size,
+    Quality,
+    CostMicros,
+    CreatedAt,
+}
@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(InspirationImage::Table)
+                    .add_column(ColumnDef::new(InspirationImage::Width).integer())
+                    .add_column(ColumnDef::new(InspirationImage::Height).integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(InspirationImage::Table)
+                    .drop_column(InspirationImage::Width)
+                    .drop_column(InspirationImage::Height)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum InspirationImage {
+    Table,
+    Width,
+    Height,
+}
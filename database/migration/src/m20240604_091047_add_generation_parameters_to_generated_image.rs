@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(GeneratedImage::Table)
+                    .add_column(ColumnDef::new(GeneratedImage::Size).string())
+                    .add_column(ColumnDef::new(GeneratedImage::Quality).string())
+                    .add_column(ColumnDef::new(GeneratedImage::Style).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Until now every image was generated at standard quality with
+        // DALL·E 3's default vivid style. The size could be set per message,
+        // so it's taken from the recorded cost when there is one and is
+        // otherwise the default 1024x1024.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE generated_image
+                SET size = coalesce(
+                        (SELECT generation_cost.size
+                        FROM generation_cost
+                        WHERE generation_cost.idempotency_key = generated_image.idempotency_key
+                            AND generation_cost.model = 'dall-e-3'
+                        ORDER BY generation_cost.id DESC
+                        LIMIT 1),
+                        '1024x1024'
+                    ),
+                    quality = 'standard',
+                    style = 'vivid'"#,
+            )
+            .await
+            .map(|_| ())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(GeneratedImage::Table)
+                    .drop_column(GeneratedImage::Size)
+                    .drop_column(GeneratedImage::Quality)
+                    .drop_column(GeneratedImage::Style)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum GeneratedImage {
+    Table,
+    Size,
+    Quality,
+    Style,
+}
@@ -1,6 +1,118 @@
+use migration::MigrationCommand;
 use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::Database;
 
+/// `up` (the default), `down`, `fresh`, `refresh` and `reset` take the
+/// migration lock first, so `up` is safe to run from every instance on start.
+/// `plan` prints the SQL pending migrations would run. Everything else goes
+/// to the sea-orm migration CLI.
 #[tokio::main]
 async fn main() {
-    cli::run_cli(migration::Migrator).await;
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|e| exit_with(&e));
+    let command = match args.command.as_deref() {
+        None | Some("up") => MigrationCommand::Up(args.steps),
+        // Like the sea-orm CLI, `down` rolls back one migration by default.
+        Some("down") => MigrationCommand::Down(Some(args.steps.unwrap_or(1))),
+        Some("fresh") => MigrationCommand::Fresh,
+        Some("refresh") => MigrationCommand::Refresh,
+        Some("reset") => MigrationCommand::Reset,
+        Some("plan") => {
+            args.check_no_others().unwrap_or_else(|e| exit_with(&e));
+            let db = connect(args.database_url).await;
+            let planned = migration::plan(&db)
+                .await
+                .expect("Error planning migrations");
+            if planned.is_empty() {
+                println!("No pending migrations");
+            }
+            for migration in planned {
+                println!("-- {}", migration.name);
+                for statement in migration.statements {
+                    println!("{statement};");
+                }
+                println!();
+            }
+            return;
+        }
+        Some(_) => return cli::run_cli(migration::Migrator).await,
+    };
+
+    args.check_no_others().unwrap_or_else(|e| exit_with(&e));
+    let db = connect(args.database_url).await;
+    migration::run_locked(&db, command)
+        .await
+        .expect("Error running migrations");
+    println!("Migrations applied");
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(2)
+}
+
+/// The options the locked commands and `plan` take. Other commands are
+/// handed to the sea-orm CLI, which parses the arguments itself.
+#[derive(Debug, Default)]
+struct Args {
+    command: Option<String>,
+    steps: Option<u32>,
+    database_url: Option<String>,
+    /// Arguments that aren't understood here.
+    others: Vec<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with('-') => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            match flag {
+                "-n" | "--num" => {
+                    let value = option_value(flag, inline_value, &mut args)?;
+                    let steps = value
+                        .parse()
+                        .map_err(|_| format!("{flag} takes a number, got {value}"))?;
+                    parsed.steps = Some(steps);
+                }
+                "-u" | "--database-url" => {
+                    parsed.database_url = Some(option_value(flag, inline_value, &mut args)?);
+                }
+                _ if parsed.command.is_none() && !flag.starts_with('-') => {
+                    parsed.command = Some(arg.clone());
+                }
+                _ => parsed.others.push(arg.clone()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn check_no_others(&self) -> Result<(), String> {
+        if self.others.is_empty() {
+            return Ok(());
+        }
+        Err(format!("Unsupported arguments: {}", self.others.join(" ")))
+    }
+}
+
+/// The value given as `--flag=value`, or else the next argument.
+fn option_value(
+    flag: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<String, String> {
+    inline_value
+        .or_else(|| args.next())
+        .ok_or_else(|| format!("{flag} needs a value"))
+}
+
+async fn connect(database_url: Option<String>) -> sea_orm_migration::sea_orm::DatabaseConnection {
+    let database_url = database_url
+        .or_else(|| std::env::var("DATABASE_URL").ok())
+        .expect("DATABASE_URL must be set, or passed with -u/--database-url");
+    Database::connect(&database_url)
+        .await
+        .expect("Failed to connect to the database")
 }
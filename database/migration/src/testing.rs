@@ -0,0 +1,23 @@
+//! Migrated databases for the integration tests of every crate.
+
+use database::testing::{self, TestDatabase};
+use sea_orm_migration::sea_orm::Database;
+use testcontainers::images::generic::GenericImage;
+
+use crate::migrate;
+
+/// Postgres with pgvector and every migration applied.
+pub async fn migrated_database() -> TestDatabase {
+    migrated_database_with(testing::postgres()).await
+}
+
+/// Like [`migrated_database`], for another image such as
+/// [`testing::postgres_with_pgmq`].
+pub async fn migrated_database_with(image: GenericImage) -> TestDatabase {
+    let database = TestDatabase::start_with(image).await;
+    let migration_connection = Database::connect(&database.connection_string)
+        .await
+        .unwrap();
+    migrate(&migration_connection, None).await.unwrap();
+    database
+}
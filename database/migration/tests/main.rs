@@ -0,0 +1,177 @@
+#[cfg(test)]
+mod tests {
+    use database::testing::TestDatabase;
+    use migration::sea_orm::{ConnectionTrait, Database, DatabaseConnection, DbBackend, Statement};
+    use migration::{
+        check_schema, migrate, plan, run_locked, schema_status, MigrationCommand, Migrator,
+        MigratorTrait,
+    };
+
+    async fn connect(database: &TestDatabase) -> DatabaseConnection {
+        Database::connect(&database.connection_string)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_migrations_wait_for_the_lock() {
+        let database = TestDatabase::start().await;
+        let first = connect(&database).await;
+        let second = connect(&database).await;
+
+        assert!(check_schema(&first).await.is_err());
+
+        let (first_result, second_result) =
+            tokio::join!(migrate(&first, None), migrate(&second, None));
+        first_result.unwrap();
+        second_result.unwrap();
+
+        check_schema(&first).await.unwrap();
+        assert_eq!(
+            Migrator::get_applied_migrations(&first)
+                .await
+                .unwrap()
+                .len(),
+            Migrator::migrations().len()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan_reports_pending_sql_without_applying_it() {
+        let database = TestDatabase::start().await;
+        let db = connect(&database).await;
+
+        let migrations = Migrator::migrations();
+        let last = migrations.last().unwrap().name().to_string();
+        migrate(&db, Some(migrations.len() as u32 - 1))
+            .await
+            .unwrap();
+
+        let planned = plan(&db).await.unwrap();
+        assert_eq!(planned.len(), 1);
+        assert_eq!(planned[0].name, last);
+        assert!(!planned[0].statements.is_empty());
+
+        let status = schema_status(&db).await.unwrap();
+        assert_eq!(status.pending, vec![last.clone()]);
+        assert!(status.unknown.is_empty());
+
+        // Rolling back goes through the lock too, one migration by default.
+        migrate(&db, None).await.unwrap();
+        run_locked(&db, MigrationCommand::Down(Some(1)))
+            .await
+            .unwrap();
+        assert_eq!(schema_status(&db).await.unwrap().pending, vec![last]);
+    }
+
+    #[tokio::test]
+    async fn test_plan_runs_nothing_against_the_database() {
+        let database = TestDatabase::start().await;
+        let db = connect(&database).await;
+
+        let planned = plan(&db).await.unwrap();
+        assert_eq!(planned.len(), Migrator::migrations().len());
+        assert!(planned[0].statements[0].contains("CREATE TABLE"));
+
+        let tables = db
+            .query_one(Statement::from_string(
+                DbBackend::Postgres,
+                "SELECT to_regclass('inspiration_image') IS NULL AS missing".to_string(),
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(tables.try_get::<bool>("", "missing").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_generated_images_get_their_storage_key_back() {
+        let database = TestDatabase::start().await;
+        let db = connect(&database).await;
+
+        let storage_key = Migrator::migrations()
+            .iter()
+            .position(|migration| {
+                migration.name() == "m20240402_190318_add_storage_key_to_generated_image"
+            })
+            .unwrap();
+        migrate(&db, Some(storage_key as u32)).await.unwrap();
+        db.execute_unprepared(
+            r#"INSERT INTO inspiration_image (id, source_url, source_id)
+            VALUES (1, 'https://example.com/1.jpg', 'source-1');
+            INSERT INTO generated_image (id, source_url, inspiration_image_id, prompt, revised_prompt)
+            VALUES
+                (1, 'https://images.s3.amazonaws.com/1_0b1c4a5e', 1, 'a prompt', 'a prompt'),
+                (2, '/files/generated/1.png', 1, 'a prompt', 'a prompt')"#,
+        )
+        .await
+        .unwrap();
+
+        migrate(&db, None).await.unwrap();
+
+        let keys: Vec<Option<String>> = db
+            .query_all(Statement::from_string(
+                DbBackend::Postgres,
+                "SELECT storage_key FROM generated_image ORDER BY id".to_string(),
+            ))
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.try_get("", "storage_key").unwrap())
+            .collect();
+        assert_eq!(keys, vec![Some("1_0b1c4a5e".to_string()), None]);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_generated_images_get_their_generation_parameters_back() {
+        let database = TestDatabase::start().await;
+        let db = connect(&database).await;
+
+        let parameters = Migrator::migrations()
+            .iter()
+            .position(|migration| {
+                migration.name() == "m20240604_091047_add_generation_parameters_to_generated_image"
+            })
+            .unwrap();
+        migrate(&db, Some(parameters as u32)).await.unwrap();
+        db.execute_unprepared(
+            r#"INSERT INTO inspiration_image (id, source_url, source_id)
+            VALUES (1, 'https://example.com/1.jpg', 'source-1');
+            INSERT INTO generated_image
+                (id, source_url, inspiration_image_id, prompt, revised_prompt, idempotency_key)
+            VALUES
+                (1, '/files/1.png', 1, 'a prompt', 'a prompt', 'generate:1'),
+                (2, '/files/2.png', 1, 'a prompt', 'a prompt', NULL);
+            INSERT INTO generation_cost
+                (inspiration_image_id, idempotency_key, backend, model, size, quality, cost_micros)
+            VALUES (1, 'generate:1', 'openai', 'dall-e-3', '1792x1024', 'standard', 80000)"#,
+        )
+        .await
+        .unwrap();
+
+        migrate(&db, None).await.unwrap();
+
+        let parameters: Vec<(String, String, String)> = db
+            .query_all(Statement::from_string(
+                DbBackend::Postgres,
+                "SELECT size, quality, style FROM generated_image ORDER BY id".to_string(),
+            ))
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| {
+                (
+                    row.try_get("", "size").unwrap(),
+                    row.try_get("", "quality").unwrap(),
+                    row.try_get("", "style").unwrap(),
+                )
+            })
+            .collect();
+        let expected = [
+            ("1792x1024", "standard", "vivid"),
+            ("1024x1024", "standard", "vivid"),
+        ]
+        .map(|(size, quality, style)| (size.to_string(), quality.to_string(), style.to_string()));
+        assert_eq!(parameters, expected.to_vec());
+    }
+}
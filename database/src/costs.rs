@@ -0,0 +1,345 @@
+//! What generations cost and the budgets that cap them. The generator saves a
+//! `generation_cost` row, priced from a [`PriceTable`], for every image it is
+//! billed for, and stops reading the queue while the day's or month's spend
+//! is over its [`Budget`]. Amounts are kept in micros, millionths of a US
+//! dollar, so they add up exactly.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use sea_orm::{
+    ActiveModelTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr, FromQueryResult, Set,
+    Statement,
+};
+
+use crate::entity::generation_cost::{
+    ActiveModel as GenerationCostActiveModel, Model as GenerationCostModel,
+};
+
+pub const MICROS_PER_USD: i64 = 1_000_000;
+
+pub fn usd_to_micros(usd: f64) -> i64 {
+    (usd * MICROS_PER_USD as f64).round() as i64
+}
+
+pub fn micros_to_usd(micros: i64) -> f64 {
+    micros as f64 / MICROS_PER_USD as f64
+}
+
+/// What a generation is priced by.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PriceKey {
+    pub backend: String,
+    pub model: String,
+    pub size: String,
+    pub quality: String,
+}
+
+impl PriceKey {
+    pub fn new(backend: &str, model: &str, size: &str, quality: &str) -> Self {
+        Self {
+            backend: backend.to_string(),
+            model: model.to_string(),
+            size: size.to_string(),
+            quality: quality.to_string(),
+        }
+    }
+}
+
+impl Display for PriceKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}/{}",
+            self.backend, self.model, self.size, self.quality
+        )
+    }
+}
+
+/// The price of one image for each backend, model, size and quality.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriceTable {
+    prices: BTreeMap<PriceKey, i64>,
+}
+
+impl Default for PriceTable {
+    /// OpenAI's list prices for DALL·E 3, DALL·E 2 variations and an
+    /// estimate for captioning one low detail image with GPT-4o.
+    fn default() -> Self {
+        Self::new(
+            [
+                ("openai/dall-e-3/1024x1024/standard", 0.040),
+                ("openai/dall-e-3/1792x1024/standard", 0.080),
+                ("openai/dall-e-3/1024x1792/standard", 0.080),
+                ("openai/dall-e-3/1024x1024/hd", 0.080),
+                ("openai/dall-e-3/1792x1024/hd", 0.120),
+                ("openai/dall-e-3/1024x1792/hd", 0.120),
+                ("openai/dall-e-2/256x256/standard", 0.016),
+                ("openai/dall-e-2/512x512/standard", 0.018),
+                ("openai/dall-e-2/1024x1024/standard", 0.020),
+                ("openai/gpt-4o/caption/low", 0.005),
+            ]
+            .into_iter()
+            .map(|(key, usd)| {
+                let key = parse_price_key(key).expect("default price keys are valid");
+                (key, usd_to_micros(usd))
+            }),
+        )
+    }
+}
+
+impl PriceTable {
+    pub fn new(prices: impl IntoIterator<Item = (PriceKey, i64)>) -> Self {
+        Self {
+            prices: prices.into_iter().collect(),
+        }
+    }
+
+    /// The default prices, overridden or extended by `GENERATION_PRICES`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut table = Self::default();
+        if let Ok(overrides) = std::env::var("GENERATION_PRICES") {
+            table = table.with_overrides(&overrides)?;
+        }
+        Ok(table)
+    }
+
+    /// Applies comma separated `backend/model/size/quality=usd` entries, e.g.
+    /// `openai/dall-e-3/1024x1024/hd=0.08`.
+    pub fn with_overrides(mut self, overrides: &str) -> anyhow::Result<Self> {
+        for entry in overrides
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            let (key, usd) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Price {entry} should look like key=usd"))?;
+            let usd: f64 = usd
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid price in {entry}"))?;
+            if usd < 0.0 {
+                return Err(anyhow!("Price in {entry} is negative"));
+            }
+            self.prices
+                .insert(parse_price_key(key.trim())?, usd_to_micros(usd));
+        }
+        Ok(self)
+    }
+
+    pub fn price(&self, key: &PriceKey) -> Option<i64> {
+        self.prices.get(key).copied()
+    }
+
+    /// The price of `key`, or the highest price in the table when it isn't
+    /// listed, so an unknown combination can't slip under the budget.
+    pub fn cost(&self, key: &PriceKey) -> i64 {
+        self.price(key)
+            .unwrap_or_else(|| self.prices.values().copied().max().unwrap_or(0))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&PriceKey, i64)> {
+        self.prices.iter().map(|(key, micros)| (key, *micros))
+    }
+}
+
+fn parse_price_key(key: &str) -> anyhow::Result<PriceKey> {
+    match key.split('/').collect::<Vec<_>>()[..] {
+        [backend, model, size, quality] => Ok(PriceKey::new(backend, model, size, quality)),
+        _ => Err(anyhow!(
+            "Price key {key} should look like backend/model/size/quality"
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetPeriod {
+    Day,
+    Month,
+}
+
+impl Display for BudgetPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetPeriod::Day => f.write_str("daily"),
+            BudgetPeriod::Month => f.write_str("monthly"),
+        }
+    }
+}
+
+/// Spend caps for generation, in UTC days and calendar months. A cap left
+/// unset doesn't apply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    pub daily_micros: Option<i64>,
+    pub monthly_micros: Option<i64>,
+}
+
+impl Budget {
+    /// Reads `GENERATION_DAILY_BUDGET_USD` and `GENERATION_MONTHLY_BUDGET_USD`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
+        let cap = |name: &str| -> anyhow::Result<Option<i64>> {
+            match lookup(name).filter(|value| !value.is_empty()) {
+                Some(value) => {
+                    let usd: f64 = value
+                        .parse()
+                        .with_context(|| format!("Invalid {name}: {value}"))?;
+                    // A negative cap would quietly block every generation.
+                    if !usd.is_finite() || usd < 0.0 {
+                        bail!("{name} must be zero or more: {value}");
+                    }
+                    Ok(Some(usd_to_micros(usd)))
+                }
+                None => Ok(None),
+            }
+        };
+        Ok(Self {
+            daily_micros: cap("GENERATION_DAILY_BUDGET_USD")?,
+            monthly_micros: cap("GENERATION_MONTHLY_BUDGET_USD")?,
+        })
+    }
+
+    /// The first cap `spend` has reached, if any.
+    pub fn exceeded(&self, spend: &Spend) -> Option<BudgetPeriod> {
+        let reached = |cap: Option<i64>, spent: i64| cap.is_some_and(|cap| spent >= cap);
+        if reached(self.daily_micros, spend.today_micros) {
+            Some(BudgetPeriod::Day)
+        } else if reached(self.monthly_micros, spend.month_micros) {
+            Some(BudgetPeriod::Month)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Spend {
+    pub today_micros: i64,
+    pub month_micros: i64,
+}
+
+pub fn start_of_day(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&now.date_naive().and_hms_opt(0, 0, 0).expect("midnight"))
+}
+
+pub fn start_of_month(now: DateTime<Utc>) -> DateTime<Utc> {
+    start_of_day(now.with_day(1).expect("every month has a first day"))
+}
+
+#[derive(Debug, Clone)]
+pub struct NewGenerationCost {
+    pub inspiration_image_id: i32,
+    pub idempotency_key: Option<String>,
+    pub key: PriceKey,
+    pub cost_micros: i64,
+}
+
+pub async fn record_cost<C: ConnectionTrait>(
+    db: &C,
+    cost: NewGenerationCost,
+) -> Result<GenerationCostModel, DbErr> {
+    GenerationCostActiveModel {
+        inspiration_image_id: Set(cost.inspiration_image_id),
+        idempotency_key: Set(cost.idempotency_key),
+        backend: Set(cost.key.backend),
+        model: Set(cost.key.model),
+        size: Set(cost.key.size),
+        quality: Set(cost.key.quality),
+        cost_micros: Set(cost.cost_micros),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+#[derive(Debug, FromQueryResult)]
+struct Total {
+    total: i64,
+}
+
+pub async fn spend_since(db: &DatabaseConnection, since: DateTime<Utc>) -> Result<i64, DbErr> {
+    let row = Total::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT coalesce(sum(cost_micros), 0)::bigint AS total
+        FROM generation_cost
+        WHERE created_at >= $1"#,
+        [since.into()],
+    ))
+    .one(db)
+    .await?;
+
+    Ok(row.map(|row| row.total).unwrap_or(0))
+}
+
+pub async fn current_spend(db: &DatabaseConnection, now: DateTime<Utc>) -> Result<Spend, DbErr> {
+    Ok(Spend {
+        today_micros: spend_since(db, start_of_day(now)).await?,
+        month_micros: spend_since(db, start_of_month(now)).await?,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, FromQueryResult)]
+pub struct DailySpend {
+    pub day: NaiveDate,
+    pub generations: i64,
+    pub cost_micros: i64,
+}
+
+/// Spend per UTC day since `since`, most recent first. Days without any
+/// generations are left out.
+pub async fn daily_spend(
+    db: &DatabaseConnection,
+    since: DateTime<Utc>,
+) -> Result<Vec<DailySpend>, DbErr> {
+    DailySpend::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT (created_at AT TIME ZONE 'UTC')::date AS day,
+            count(*) AS generations,
+            sum(cost_micros)::bigint AS cost_micros
+        FROM generation_cost
+        WHERE created_at >= $1
+        GROUP BY day
+        ORDER BY day DESC"#,
+        [since.into()],
+    ))
+    .all(db)
+    .await
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, FromQueryResult)]
+pub struct PriceSpend {
+    pub backend: String,
+    pub model: String,
+    pub size: String,
+    pub quality: String,
+    pub generations: i64,
+    pub cost_micros: i64,
+}
+
+/// Spend since `since` for each backend, model, size and quality, most
+/// expensive first.
+pub async fn spend_by_price(
+    db: &DatabaseConnection,
+    since: DateTime<Utc>,
+) -> Result<Vec<PriceSpend>, DbErr> {
+    PriceSpend::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT backend, model, size, quality,
+            count(*) AS generations,
+            sum(cost_micros)::bigint AS cost_micros
+        FROM generation_cost
+        WHERE created_at >= $1
+        GROUP BY backend, model, size, quality
+        ORDER BY cost_micros DESC, backend, model, size, quality"#,
+        [since.into()],
+    ))
+    .all(db)
+    .await
+}
@@ -0,0 +1,113 @@
+//! Embeddings live in the `image_embedding` table created by the pgvector
+//! migration. sea-orm has no vector type, so everything here is raw SQL with
+//! vectors passed as text literals.
+
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, DbErr, FromQueryResult, Statement};
+use serde::Serialize;
+
+use crate::prompt_drift::VARIATION_SOURCE;
+
+pub const EMBEDDING_DIMENSIONS: usize = 1536;
+
+#[derive(Debug, Serialize, FromQueryResult)]
+pub struct SimilarImage {
+    pub generated_image_id: i32,
+    pub revised_prompt: String,
+    /// Cosine distance to the source image, 0 for identical embeddings.
+    pub distance: f64,
+}
+
+/// The text embedded for a generated image: the description it was made
+/// from followed by the prompt DALL·E actually used.
+pub fn embedding_input(description: Option<&str>, revised_prompt: &str) -> String {
+    match description {
+        Some(description) if !description.trim().is_empty() => {
+            format!("{}\n{}", description.trim(), revised_prompt.trim())
+        }
+        _ => revised_prompt.trim().to_string(),
+    }
+}
+
+/// Formats an embedding the way pgvector parses it, e.g. `[0.1,0.2]`.
+pub fn to_vector_literal(embedding: &[f32]) -> String {
+    let values: Vec<String> = embedding.iter().map(|value| value.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+pub async fn save_embedding(
+    db: &DatabaseConnection,
+    generated_image_id: i32,
+    model: &str,
+    embedding: &[f32],
+) -> Result<(), DbErr> {
+    if embedding.len() != EMBEDDING_DIMENSIONS {
+        return Err(DbErr::Custom(format!(
+            "Expected {EMBEDDING_DIMENSIONS} dimensions, got {}",
+            embedding.len()
+        )));
+    }
+
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"INSERT INTO image_embedding (generated_image_id, model, embedding)
+        VALUES ($1, $2, $3::vector)
+        ON CONFLICT (generated_image_id)
+        DO UPDATE SET model = EXCLUDED.model, embedding = EXCLUDED.embedding, created_at = now()"#,
+        [
+            generated_image_id.into(),
+            model.into(),
+            to_vector_literal(embedding).into(),
+        ],
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Nearest neighbours of a generated image by cosine distance. Images without
+/// an embedding have no neighbours.
+pub async fn find_similar(
+    db: &DatabaseConnection,
+    generated_image_id: i32,
+    limit: u64,
+) -> Result<Vec<SimilarImage>, DbErr> {
+    SimilarImage::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"WITH target AS (
+            SELECT embedding FROM image_embedding WHERE generated_image_id = $1
+        )
+        SELECT
+            generated_image.id AS generated_image_id,
+            generated_image.revised_prompt,
+            (image_embedding.embedding <=> target.embedding)::float8 AS distance
+        FROM image_embedding
+        CROSS JOIN target
+        JOIN generated_image ON generated_image.id = image_embedding.generated_image_id
+        WHERE image_embedding.generated_image_id <> $1
+        ORDER BY image_embedding.embedding <=> target.embedding
+        LIMIT $2"#,
+        [generated_image_id.into(), (limit as i64).into()],
+    ))
+    .all(db)
+    .await
+}
+
+/// Ids of prompted generated images that don't have an embedding yet.
+pub async fn missing_embeddings(db: &DatabaseConnection) -> Result<Vec<i32>, DbErr> {
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"SELECT generated_image.id FROM generated_image
+            LEFT JOIN image_embedding ON image_embedding.generated_image_id = generated_image.id
+            WHERE image_embedding.generated_image_id IS NULL
+            AND btrim(generated_image.revised_prompt) <> ''
+            AND generated_image.generation_source IS DISTINCT FROM $1
+            ORDER BY generated_image.id"#,
+            [VARIATION_SOURCE.into()],
+        ))
+        .await?;
+
+    rows.iter()
+        .map(|row| row.try_get::<i32>("", "id"))
+        .collect()
+}
@@ -3,7 +3,7 @@
 use sea_orm::entity::prelude::*;
 use serde::Serialize;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize)]
 #[sea_orm(table_name = "generated_image")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -12,9 +12,33 @@ pub struct Model {
     pub inspiration_image_id: i32,
     pub prompt: String,
     pub revised_prompt: String,
+    pub storage_key: Option<String>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub prompt_similarity: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub prompt_length_ratio: Option<f64>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub added_concepts: Option<Json>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub removed_concepts: Option<Json>,
+    #[sea_orm(unique)]
+    pub idempotency_key: Option<String>,
+    pub size: Option<String>,
+    pub quality: Option<String>,
+    pub style: Option<String>,
+    pub generation_source: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::collection_item::Entity")]
+    CollectionItem,
+}
+
+impl Related<super::collection_item::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CollectionItem.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
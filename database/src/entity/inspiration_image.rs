@@ -1,5 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
 
+use super::sea_orm_active_enums::GenerationStatus;
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -10,6 +11,20 @@ pub struct Model {
     pub source_url: String,
     pub source_id: String,
     pub description: Option<String>,
+    pub generation_status: Option<GenerationStatus>,
+    pub generation_error: Option<String>,
+    pub archived_key: Option<String>,
+    pub archived_url: Option<String>,
+    pub content_hash: Option<String>,
+    pub mime_type: Option<String>,
+    pub photographer_name: Option<String>,
+    pub photographer_url: Option<String>,
+    pub upstream_available: Option<bool>,
+    pub upstream_checked_at: Option<DateTimeWithTimeZone>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// Written by a vision model when an image is generated from it.
+    pub caption: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -2,5 +2,14 @@
 
 pub mod prelude;
 
+pub mod collection;
+pub mod collection_item;
 pub mod generated_image;
+pub mod generation_cost;
+pub mod image_derivative;
+pub mod image_tag;
 pub mod inspiration_image;
+pub mod outbox_message;
+pub mod reconciliation_run;
+pub mod sea_orm_active_enums;
+pub mod tag;
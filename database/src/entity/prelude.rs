@@ -1,4 +1,12 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
 
+pub use super::collection::Entity as Collection;
+pub use super::collection_item::Entity as CollectionItem;
 pub use super::generated_image::Entity as GeneratedImage;
+pub use super::generation_cost::Entity as GenerationCost;
+pub use super::image_derivative::Entity as ImageDerivative;
+pub use super::image_tag::Entity as ImageTag;
 pub use super::inspiration_image::Entity as InspirationImage;
+pub use super::outbox_message::Entity as OutboxMessage;
+pub use super::reconciliation_run::Entity as ReconciliationRun;
+pub use super::tag::Entity as Tag;
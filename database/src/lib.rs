@@ -1,44 +1,190 @@
-use pgmq::PGMQueue;
-use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbErr};
+use anyhow::{anyhow, Context};
+use messages::{GenerateImageEnvelope, GenerationParameters, GENERATE_IMAGE_DLQ};
+use pool::PoolConfig;
+use queue::{MessageQueue, PgmqExtensionQueue, PgmqQueue};
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbErr};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
-use tracing::log;
+use std::sync::Arc;
 
+pub mod costs;
+pub mod embedding;
 pub mod entity;
+pub mod messages;
+pub mod outbox;
+pub mod pool;
+pub mod pool_metrics;
+pub mod prompt_drift;
+pub mod queue;
+pub mod reconciliation;
+pub mod repository;
+pub mod tags;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
+/// Connects with the default [`PoolConfig`]. Services use
+/// [`pool::connect`] with their own settings.
 pub async fn get_connection(database_url: &str) -> Result<DatabaseConnection, DbErr> {
-    let mut opt = ConnectOptions::new(database_url.to_owned());
-    opt.max_connections(100)
-        .min_connections(5)
-        .connect_timeout(Duration::from_secs(10))
-        .acquire_timeout(Duration::from_secs(10))
-        .idle_timeout(Duration::from_secs(10))
-        .max_lifetime(Duration::from_secs(10))
-        .sqlx_logging(true)
-        .sqlx_logging_level(log::LevelFilter::Info);
-    return Database::connect(opt).await;
-}
-
-#[derive(Serialize, Debug, Deserialize)]
+    pool::connect(database_url, &PoolConfig::default()).await
+}
+
+pub const GENERATE_IMAGE_QUEUE: &str = "generate_image";
+pub const GENERATE_IMAGE_PRIORITY_QUEUE: &str = "generate_image_priority";
+
+/// Generate requests are split into lanes, each its own pgmq queue, so a few
+/// requests made by hand aren't stuck behind the daily batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lane {
+    Priority,
+    Batch,
+}
+
+impl Lane {
+    pub const ALL: [Lane; 2] = [Lane::Priority, Lane::Batch];
+
+    pub fn queue_name(self) -> &'static str {
+        match self {
+            Lane::Priority => GENERATE_IMAGE_PRIORITY_QUEUE,
+            Lane::Batch => GENERATE_IMAGE_QUEUE,
+        }
+    }
+
+    /// How many reads the lane gets for each read of a weight 1 lane while
+    /// both have work waiting.
+    pub fn default_weight(self) -> u32 {
+        match self {
+            Lane::Priority => 4,
+            Lane::Batch => 1,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
 pub struct GenerateImageMessage {
     pub inspiration_image_id: i32,
+    /// Set on requests made by hand, such as regenerations, so they aren't
+    /// mistaken for redeliveries of the original generation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "GenerationParameters::is_empty")]
+    pub parameters: GenerationParameters,
+}
+
+impl GenerateImageMessage {
+    pub fn new(inspiration_image_id: i32) -> Self {
+        Self {
+            inspiration_image_id,
+            request_id: None,
+            parameters: GenerationParameters::default(),
+        }
+    }
+
+    /// Wraps the message in the current envelope version, ready to publish.
+    pub fn into_envelope(self) -> GenerateImageEnvelope {
+        GenerateImageEnvelope::new(self)
+    }
+
+    /// Identifies the generation a message asks for, so redeliveries of the
+    /// same message can be recognised.
+    pub fn idempotency_key(&self) -> String {
+        match &self.request_id {
+            Some(request_id) => {
+                format!("generate_image:{}:{request_id}", self.inspiration_image_id)
+            }
+            None => format!("generate_image:{}", self.inspiration_image_id),
+        }
+    }
+}
+
+/// Where pgmq runs. Small deployments can install it in the main database
+/// with `MESSAGE_QUEUE_IN_MAIN_DATABASE=true`; otherwise it is its own
+/// instance at `MESSAGE_QUEUE_URL`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueBackend {
+    Separate(String),
+    MainDatabase,
 }
 
-#[derive(Debug, Clone)]
-pub struct GenerateImageQueue {
-    pub queue: PGMQueue,
-    pub queue_name: String,
+impl QueueBackend {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Exactly one of the two settings has to be given, so a missing URL
+    /// can't quietly put the queue in the main database.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
+        let url = lookup("MESSAGE_QUEUE_URL").filter(|url| !url.is_empty());
+        let in_main_database = match lookup("MESSAGE_QUEUE_IN_MAIN_DATABASE").as_deref() {
+            None | Some("") | Some("false") => false,
+            Some("true") => true,
+            Some(value) => {
+                return Err(anyhow!(
+                    "MESSAGE_QUEUE_IN_MAIN_DATABASE must be true or false, got {value}"
+                ))
+            }
+        };
+        match (url, in_main_database) {
+            (Some(_), true) => Err(anyhow!(
+                "Set MESSAGE_QUEUE_URL or MESSAGE_QUEUE_IN_MAIN_DATABASE, not both"
+            )),
+            (Some(url), false) => Ok(QueueBackend::Separate(url)),
+            (None, true) => Ok(QueueBackend::MainDatabase),
+            (None, false) => Err(anyhow!(
+                "MESSAGE_QUEUE_URL must be set, or MESSAGE_QUEUE_IN_MAIN_DATABASE=true to run the queue in the main database"
+            )),
+        }
+    }
+}
+
+/// Connects to the queue for `backend`, sharing `db` when pgmq runs in the
+/// main database, in which case the extension is installed if it isn't yet.
+pub async fn connect_queue(
+    db: &DatabaseConnection,
+    backend: &QueueBackend,
+) -> anyhow::Result<Arc<dyn MessageQueue>> {
+    match backend {
+        QueueBackend::Separate(url) => {
+            get_queue_connection(url.clone(), GENERATE_IMAGE_QUEUE.to_string()).await
+        }
+        QueueBackend::MainDatabase => {
+            db.execute_unprepared("CREATE EXTENSION IF NOT EXISTS pgmq CASCADE")
+                .await
+                .map_err(|e| {
+                    anyhow!(
+                        "Error installing pgmq in the main database, which needs an image that ships it: {e}"
+                    )
+                })?;
+            Ok(Arc::new(PgmqExtensionQueue::new(db.clone())))
+        }
+    }
 }
 
-pub async fn get_queue_connection(database_url: String, queue_name: String) -> GenerateImageQueue {
-    let queue = PGMQueue::new(database_url)
+pub async fn get_queue_connection(
+    database_url: String,
+    queue_name: String,
+) -> anyhow::Result<Arc<dyn MessageQueue>> {
+    let queue = PgmqQueue::new(database_url)
         .await
-        .expect("Failed to connect message queue to postgres");
+        .context("Failed to connect message queue to postgres")?;
 
     queue
         .create(&queue_name)
         .await
-        .expect("Error creating generate image queue");
+        .context("Error creating generate image queue")?;
+
+    Ok(Arc::new(queue))
+}
 
-    GenerateImageQueue { queue, queue_name }
+/// Creates the queue for every lane along with the dead letter queue.
+pub async fn create_generate_image_queues(queue: &dyn MessageQueue) -> anyhow::Result<()> {
+    for queue_name in Lane::ALL
+        .map(Lane::queue_name)
+        .into_iter()
+        .chain([GENERATE_IMAGE_DLQ])
+    {
+        queue
+            .create(queue_name)
+            .await
+            .with_context(|| format!("Error creating {queue_name} queue"))?;
+    }
+    Ok(())
 }
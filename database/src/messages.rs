@@ -0,0 +1,92 @@
+//! Queue messages are wrapped in a tagged, versioned envelope so a payload can
+//! change without breaking the messages already in flight during a deploy.
+//! Producers always write the current version; consumers decode every
+//! version they still understand and dead-letter the rest.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::GenerateImageMessage;
+
+pub const GENERATE_IMAGE_MESSAGE_TYPE: &str = "generate_image";
+/// Version 1 was the bare `{ "inspiration_image_id": .. }` object without an
+/// envelope. Version 2 added the envelope and generation parameters.
+pub const GENERATE_IMAGE_MESSAGE_VERSION: u32 = 2;
+
+/// Where messages that can't be decoded end up, see [`DeadLetter`].
+pub const GENERATE_IMAGE_DLQ: &str = "generate_image_dlq";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MessageEnvelope<T> {
+    #[serde(rename = "type")]
+    pub message_type: String,
+    pub version: u32,
+    /// Follows one piece of work across services and redeliveries.
+    pub correlation_id: String,
+    pub enqueued_at: DateTime<Utc>,
+    pub payload: T,
+}
+
+pub type GenerateImageEnvelope = MessageEnvelope<GenerateImageMessage>;
+
+impl GenerateImageEnvelope {
+    pub fn new(payload: GenerateImageMessage) -> Self {
+        Self {
+            message_type: GENERATE_IMAGE_MESSAGE_TYPE.to_string(),
+            version: GENERATE_IMAGE_MESSAGE_VERSION,
+            correlation_id: new_correlation_id(),
+            enqueued_at: Utc::now(),
+            payload,
+        }
+    }
+
+    /// Ties the message to the run that produced it, such as one collection
+    /// run, instead of a fresh id.
+    pub fn with_correlation_id(mut self, correlation_id: impl Into<String>) -> Self {
+        self.correlation_id = correlation_id.into();
+        self
+    }
+}
+
+pub fn new_correlation_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Optional overrides for a single generation. Anything left out uses the
+/// generator's defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct GenerationParameters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// A size the backend supports, or `auto` to match the inspiration
+    /// image's aspect ratio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    /// What the image is generated from: `description`, `vision` to caption
+    /// the inspiration image, or `variation` to vary it directly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Used instead of the inspiration image's description.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_override: Option<String>,
+}
+
+impl GenerationParameters {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// A message that was taken off a queue because it couldn't be decoded,
+/// kept with the reason so it can be inspected or replayed by hand.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    pub source_queue: String,
+    pub error: String,
+    pub message: serde_json::Value,
+    pub failed_at: DateTime<Utc>,
+}
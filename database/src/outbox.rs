@@ -0,0 +1,184 @@
+//! Transactional outbox for messages that have to go out with a database
+//! change. Messages are written to `outbox_message` in the same transaction
+//! as the change and a relay publishes them to the queue afterwards, so a queue
+//! outage delays a message instead of losing it. Delivery is at least once:
+//! a message can be published twice if marking it sent fails.
+//!
+//! When pgmq runs in the main database the outbox isn't needed: [`publish`]
+//! sends straight to the queue in the caller's transaction instead.
+
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::sea_query::{Expr, LockBehavior, LockType};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set, TransactionTrait,
+};
+use serde::Serialize;
+use tracing::{event, instrument, Level};
+
+use crate::entity::outbox_message::{
+    ActiveModel as OutboxMessageActiveModel, Column as OutboxMessageColumn,
+    Entity as OutboxMessage, Model as OutboxMessageModel,
+};
+use crate::queue::{send_with, MessageQueue};
+use crate::QueueBackend;
+
+pub const DEFAULT_RELAY_BATCH_SIZE: u64 = 100;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RelaySummary {
+    pub sent: u64,
+    pub failed: u64,
+}
+
+/// Records a message to be published to `queue_name`. Pass the transaction
+/// that makes the related change so both commit or roll back together.
+pub async fn enqueue<C, T>(
+    db: &C,
+    queue_name: &str,
+    message: &T,
+) -> Result<OutboxMessageModel, DbErr>
+where
+    C: ConnectionTrait,
+    T: Serialize,
+{
+    enqueue_at(db, queue_name, message, None).await
+}
+
+/// Like [`enqueue`], but the message isn't visible to consumers until
+/// `deliver_at`. The relay still publishes it straight away, using pgmq's
+/// delayed send for the remaining time.
+pub async fn enqueue_at<C, T>(
+    db: &C,
+    queue_name: &str,
+    message: &T,
+    deliver_at: Option<DateTimeWithTimeZone>,
+) -> Result<OutboxMessageModel, DbErr>
+where
+    C: ConnectionTrait,
+    T: Serialize,
+{
+    let payload = serde_json::to_value(message).map_err(|e| DbErr::Custom(e.to_string()))?;
+
+    OutboxMessageActiveModel {
+        queue_name: Set(queue_name.to_string()),
+        payload: Set(payload),
+        deliver_at: Set(deliver_at),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// Publishes a message with a database change, going through the outbox
+/// unless pgmq lives in the same database, in which case it is sent on `db`
+/// directly. Either way it only goes out if the transaction commits.
+pub async fn publish<C, T>(
+    db: &C,
+    backend: &QueueBackend,
+    queue_name: &str,
+    message: &T,
+) -> Result<(), DbErr>
+where
+    C: ConnectionTrait,
+    T: Serialize,
+{
+    match backend {
+        QueueBackend::MainDatabase => {
+            let payload =
+                serde_json::to_value(message).map_err(|e| DbErr::Custom(e.to_string()))?;
+            send_with(db, queue_name, &payload, 0).await?;
+        }
+        QueueBackend::Separate(_) => {
+            enqueue(db, queue_name, message).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Seconds until `deliver_at`, or 0 for messages that are already due.
+pub fn delivery_delay(
+    deliver_at: Option<DateTimeWithTimeZone>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> u64 {
+    deliver_at
+        .map(|deliver_at| (deliver_at.with_timezone(&chrono::Utc) - now).num_seconds())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or_default() as u64
+}
+
+/// Publishes up to `batch_size` unsent messages in the order they were
+/// written. Rows are locked with `SKIP LOCKED` so several relays can run at
+/// once. A failed send stops the batch, leaving the rest for the next run.
+#[instrument(skip(db, queue))]
+pub async fn relay_outbox(
+    db: &DatabaseConnection,
+    queue: &dyn MessageQueue,
+    batch_size: u64,
+) -> Result<RelaySummary, DbErr> {
+    let txn = db.begin().await?;
+    let mut select = OutboxMessage::find()
+        .filter(OutboxMessageColumn::SentAt.is_null())
+        .order_by_asc(OutboxMessageColumn::Id)
+        .limit(batch_size);
+    QueryTrait::query(&mut select).lock_with_behavior(LockType::Update, LockBehavior::SkipLocked);
+    let messages = select.all(&txn).await?;
+
+    let mut summary = RelaySummary::default();
+    for message in messages {
+        let delay = delivery_delay(message.deliver_at, chrono::Utc::now());
+        let sent = if delay > 0 {
+            queue
+                .send_delay(&message.queue_name, &message.payload, delay)
+                .await
+        } else {
+            queue.send(&message.queue_name, &message.payload).await
+        };
+        match sent {
+            Ok(_) => {
+                OutboxMessage::update_many()
+                    .col_expr(
+                        OutboxMessageColumn::SentAt,
+                        Expr::current_timestamp().into(),
+                    )
+                    .col_expr(
+                        OutboxMessageColumn::Attempts,
+                        Expr::col(OutboxMessageColumn::Attempts).add(1),
+                    )
+                    .filter(OutboxMessageColumn::Id.eq(message.id))
+                    .exec(&txn)
+                    .await?;
+                summary.sent += 1;
+            }
+            Err(e) => {
+                event!(
+                    Level::WARN,
+                    "Error publishing outbox message {}: {e}",
+                    message.id
+                );
+                OutboxMessage::update_many()
+                    .col_expr(
+                        OutboxMessageColumn::Attempts,
+                        Expr::col(OutboxMessageColumn::Attempts).add(1),
+                    )
+                    .col_expr(OutboxMessageColumn::LastError, Expr::value(e.to_string()))
+                    .filter(OutboxMessageColumn::Id.eq(message.id))
+                    .exec(&txn)
+                    .await?;
+                summary.failed += 1;
+                break;
+            }
+        }
+    }
+    txn.commit().await?;
+
+    if summary.sent > 0 || summary.failed > 0 {
+        event!(
+            Level::INFO,
+            "Relayed {} outbox messages, {} failed",
+            summary.sent,
+            summary.failed
+        );
+    }
+    Ok(summary)
+}
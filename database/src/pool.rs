@@ -0,0 +1,195 @@
+//! Connection pool settings and read replica routing. Every setting can be
+//! set for all services with `DB_*` or for one with `<SERVICE>_DB_*`, e.g.
+//! `SERVER_DB_MAX_CONNECTIONS`.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbErr};
+use tracing::log;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub connect_timeout: Duration,
+    pub acquire_timeout: Duration,
+    /// How long a connection can sit unused before it is closed.
+    pub idle_timeout: Option<Duration>,
+    /// How long a connection is kept before it is replaced.
+    pub max_lifetime: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            min_connections: 1,
+            connect_timeout: Duration::from_secs(10),
+            acquire_timeout: Duration::from_secs(10),
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+        }
+    }
+}
+
+impl PoolConfig {
+    /// The defaults, overridden by `DB_*` and then `<service>_DB_*` variables.
+    /// Timeouts are in seconds, and 0 turns the idle timeout or max lifetime
+    /// off. Values that aren't whole numbers, and connection counts that don't
+    /// fit in a `u32`, are an error.
+    pub fn from_env(service: &str) -> anyhow::Result<Self> {
+        Self::from_lookup(service, |name| std::env::var(name).ok())
+    }
+
+    pub fn from_lookup(
+        service: &str,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<Self> {
+        let prefix = service.to_uppercase();
+        // The variable that was read along with its value, so errors name it.
+        let value = |name: &str| -> anyhow::Result<Option<(String, u64)>> {
+            let Some((variable, value)) = [format!("{prefix}_DB_{name}"), format!("DB_{name}")]
+                .into_iter()
+                .find_map(|variable| lookup(&variable).map(|value| (variable, value)))
+            else {
+                return Ok(None);
+            };
+            let parsed = value
+                .trim()
+                .parse()
+                .with_context(|| format!("{variable} is not a whole number: {value}"))?;
+            Ok(Some((variable, parsed)))
+        };
+        let seconds = |name: &str, default: Duration| -> anyhow::Result<Duration> {
+            Ok(value(name)?.map_or(default, |(_, seconds)| Duration::from_secs(seconds)))
+        };
+        let optional =
+            |name: &str, default: Option<Duration>| -> anyhow::Result<Option<Duration>> {
+                Ok(match value(name)? {
+                    Some((_, 0)) => None,
+                    Some((_, seconds)) => Some(Duration::from_secs(seconds)),
+                    None => default,
+                })
+            };
+        let count = |name: &str, default: u32| -> anyhow::Result<u32> {
+            match value(name)? {
+                Some((variable, count)) => u32::try_from(count)
+                    .with_context(|| format!("{variable} is too large: {count}")),
+                None => Ok(default),
+            }
+        };
+
+        let defaults = Self::default();
+        Ok(Self {
+            max_connections: count("MAX_CONNECTIONS", defaults.max_connections)?,
+            min_connections: count("MIN_CONNECTIONS", defaults.min_connections)?,
+            connect_timeout: seconds("CONNECT_TIMEOUT_SECS", defaults.connect_timeout)?,
+            acquire_timeout: seconds("ACQUIRE_TIMEOUT_SECS", defaults.acquire_timeout)?,
+            idle_timeout: optional("IDLE_TIMEOUT_SECS", defaults.idle_timeout)?,
+            max_lifetime: optional("MAX_LIFETIME_SECS", defaults.max_lifetime)?,
+        })
+    }
+
+    fn connect_options(&self, database_url: &str) -> ConnectOptions {
+        let mut opt = ConnectOptions::new(database_url.to_owned());
+        opt.max_connections(self.max_connections)
+            .min_connections(self.min_connections.min(self.max_connections))
+            .connect_timeout(self.connect_timeout)
+            .acquire_timeout(self.acquire_timeout)
+            .sqlx_logging(true)
+            .sqlx_logging_level(log::LevelFilter::Info);
+        if let Some(idle_timeout) = self.idle_timeout {
+            opt.idle_timeout(idle_timeout);
+        }
+        if let Some(max_lifetime) = self.max_lifetime {
+            opt.max_lifetime(max_lifetime);
+        }
+        opt
+    }
+}
+
+pub async fn connect(database_url: &str, config: &PoolConfig) -> Result<DatabaseConnection, DbErr> {
+    Database::connect(config.connect_options(database_url)).await
+}
+
+/// The primary and, optionally, a read replica. Read-only queries that can
+/// tolerate replication lag should use [`Databases::reader`].
+#[derive(Debug, Clone)]
+pub struct Databases {
+    primary: DatabaseConnection,
+    replica: Option<DatabaseConnection>,
+}
+
+impl Databases {
+    pub fn new(primary: DatabaseConnection) -> Self {
+        Self {
+            primary,
+            replica: None,
+        }
+    }
+
+    pub fn with_replica(mut self, replica: DatabaseConnection) -> Self {
+        self.replica = Some(replica);
+        self
+    }
+
+    /// Connects to the primary at `database_url` and, when
+    /// `DATABASE_REPLICA_URL` is set, to the replica with the same settings.
+    pub async fn connect_from_env(database_url: &str, config: &PoolConfig) -> Result<Self, DbErr> {
+        let databases = Self::new(connect(database_url, config).await?);
+        match std::env::var("DATABASE_REPLICA_URL") {
+            Ok(replica_url) if !replica_url.is_empty() => {
+                Ok(databases.with_replica(connect(&replica_url, config).await?))
+            }
+            _ => Ok(databases),
+        }
+    }
+
+    /// For writes and reads that must see them.
+    pub fn primary(&self) -> &DatabaseConnection {
+        &self.primary
+    }
+
+    /// The replica, or the primary when there isn't one.
+    pub fn reader(&self) -> &DatabaseConnection {
+        self.replica.as_ref().unwrap_or(&self.primary)
+    }
+
+    pub fn replica(&self) -> Option<&DatabaseConnection> {
+        self.replica.as_ref()
+    }
+}
+
+impl From<DatabaseConnection> for Databases {
+    fn from(primary: DatabaseConnection) -> Self {
+        Self::new(primary)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Open connections, idle or in use.
+    pub size: u32,
+    pub idle: u32,
+}
+
+impl PoolStats {
+    pub fn in_use(&self) -> u32 {
+        self.size.saturating_sub(self.idle)
+    }
+}
+
+/// `None` for connections that aren't a Postgres pool.
+pub fn pool_stats(db: &DatabaseConnection) -> Option<PoolStats> {
+    match db {
+        DatabaseConnection::SqlxPostgresPoolConnection(_) => {
+            let pool = db.get_postgres_connection_pool();
+            Some(PoolStats {
+                size: pool.size(),
+                idle: pool.num_idle() as u32,
+            })
+        }
+        _ => None,
+    }
+}
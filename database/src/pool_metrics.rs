@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use crate::pool::{pool_stats, Databases};
+use prometheus::{IntGaugeVec, Opts, Registry};
+
+const METRICS_REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Connection pool gauges, labelled `pool="primary"` or `pool="replica"`.
+#[derive(Clone)]
+pub struct PoolMetrics {
+    connections: IntGaugeVec,
+    idle_connections: IntGaugeVec,
+    in_use_connections: IntGaugeVec,
+}
+
+impl PoolMetrics {
+    pub fn register(registry: &Registry) -> prometheus::Result<Self> {
+        let gauge = |name: &str, help: &str| -> prometheus::Result<IntGaugeVec> {
+            let gauge = IntGaugeVec::new(Opts::new(name, help), &["pool"])?;
+            registry.register(Box::new(gauge.clone()))?;
+            Ok(gauge)
+        };
+
+        Ok(Self {
+            connections: gauge("db_pool_connections", "Open database connections")?,
+            idle_connections: gauge(
+                "db_pool_idle_connections",
+                "Open database connections waiting to be used",
+            )?,
+            in_use_connections: gauge(
+                "db_pool_in_use_connections",
+                "Database connections currently in use",
+            )?,
+        })
+    }
+
+    pub fn refresh(&self, databases: &Databases) {
+        let pools = [
+            ("primary", Some(databases.primary())),
+            ("replica", databases.replica()),
+        ];
+        for (label, db) in pools {
+            let Some(stats) = db.and_then(pool_stats) else {
+                continue;
+            };
+            self.connections
+                .with_label_values(&[label])
+                .set(stats.size as i64);
+            self.idle_connections
+                .with_label_values(&[label])
+                .set(stats.idle as i64);
+            self.in_use_connections
+                .with_label_values(&[label])
+                .set(stats.in_use() as i64);
+        }
+    }
+
+    /// Keeps the gauges up to date for as long as the service runs.
+    pub fn spawn_refresh(self, databases: Databases) {
+        tokio::spawn(async move {
+            loop {
+                self.refresh(&databases);
+                tokio::time::sleep(METRICS_REFRESH_INTERVAL).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,164 @@
+//! Compares the prompt we send to DALL·E with the `revised_prompt` it actually
+//! used, to see how much the model rewrites our descriptions.
+
+use std::collections::BTreeSet;
+
+use sea_orm::prelude::Json;
+use sea_orm::Set;
+use serde::Serialize;
+
+use crate::entity::generated_image::ActiveModel as GeneratedImageActiveModel;
+
+const STOP_WORDS: [&str; 40] = [
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "has", "in", "is", "it", "its",
+    "of", "on", "or", "that", "the", "this", "to", "with", "was", "were", "which", "while", "who",
+    "into", "their", "there", "they", "his", "her", "he", "she", "can", "all", "been", "over",
+];
+
+/// The `generation_source` of images made as variations of the inspiration
+/// image rather than from a prompt.
+pub const VARIATION_SOURCE: &str = "variation";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    Equal,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffSegment {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PromptDrift {
+    /// Jaccard similarity of the concept sets, from 0 (nothing shared) to 1.
+    pub similarity: f64,
+    /// Word count of the revised prompt divided by the word count of the prompt.
+    pub length_ratio: f64,
+    pub added_concepts: Vec<String>,
+    pub removed_concepts: Vec<String>,
+}
+
+impl PromptDrift {
+    /// Copies the analysis onto the drift columns of a generated image.
+    pub fn apply_to(self, generated_image: &mut GeneratedImageActiveModel) {
+        generated_image.prompt_similarity = Set(Some(self.similarity));
+        generated_image.prompt_length_ratio = Set(Some(self.length_ratio));
+        generated_image.added_concepts = Set(Some(Json::from(self.added_concepts)));
+        generated_image.removed_concepts = Set(Some(Json::from(self.removed_concepts)));
+    }
+}
+
+fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// Content words of a prompt, lowercased with punctuation and stop words removed.
+pub fn concepts(text: &str) -> BTreeSet<String> {
+    text.split_whitespace()
+        .map(normalize)
+        .filter(|word| word.len() > 1 && !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// Whether DALL·E rewrote a prompt for the image, so there is a revised
+/// prompt to compare and embed. Variations have no prompt of their own.
+pub fn is_prompted(revised_prompt: &str, generation_source: Option<&str>) -> bool {
+    !revised_prompt.trim().is_empty() && generation_source != Some(VARIATION_SOURCE)
+}
+
+pub fn analyze(prompt: &str, revised_prompt: &str) -> PromptDrift {
+    let prompt_concepts = concepts(prompt);
+    let revised_concepts = concepts(revised_prompt);
+
+    let union = prompt_concepts.union(&revised_concepts).count();
+    let intersection = prompt_concepts.intersection(&revised_concepts).count();
+    let similarity = if union == 0 {
+        1.0
+    } else {
+        intersection as f64 / union as f64
+    };
+
+    let prompt_length = prompt.split_whitespace().count();
+    let revised_length = revised_prompt.split_whitespace().count();
+    let length_ratio = if prompt_length == 0 {
+        revised_length as f64
+    } else {
+        revised_length as f64 / prompt_length as f64
+    };
+
+    PromptDrift {
+        similarity,
+        length_ratio,
+        added_concepts: revised_concepts
+            .difference(&prompt_concepts)
+            .cloned()
+            .collect(),
+        removed_concepts: prompt_concepts
+            .difference(&revised_concepts)
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Word level diff from `prompt` to `revised_prompt` using the longest common
+/// subsequence of normalized words. Adjacent words of the same kind are
+/// merged into one segment, keeping the original spelling.
+pub fn diff(prompt: &str, revised_prompt: &str) -> Vec<DiffSegment> {
+    let old: Vec<&str> = prompt.split_whitespace().collect();
+    let new: Vec<&str> = revised_prompt.split_whitespace().collect();
+    let old_normalized: Vec<String> = old.iter().map(|word| normalize(word)).collect();
+    let new_normalized: Vec<String> = new.iter().map(|word| normalize(word)).collect();
+
+    // lcs[i][j] is the LCS length of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old_normalized[i] == new_normalized[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut segments: Vec<DiffSegment> = Vec::new();
+    let mut push = |kind: DiffKind, word: &str| match segments.last_mut() {
+        Some(last) if last.kind == kind => {
+            last.text.push(' ');
+            last.text.push_str(word);
+        }
+        _ => segments.push(DiffSegment {
+            kind,
+            text: word.to_string(),
+        }),
+    };
+
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old_normalized[i] == new_normalized[j] {
+            push(DiffKind::Equal, new[j]);
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            push(DiffKind::Removed, old[i]);
+            i += 1;
+        } else {
+            push(DiffKind::Added, new[j]);
+            j += 1;
+        }
+    }
+    for word in &old[i..] {
+        push(DiffKind::Removed, word);
+    }
+    for word in &new[j..] {
+        push(DiffKind::Added, word);
+    }
+
+    segments
+}
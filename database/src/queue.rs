@@ -0,0 +1,339 @@
+//! The message queue the services talk to. Production uses pgmq; tests can
+//! use [`InMemoryQueue`] to exercise the collector and generator without a
+//! Postgres container. Messages are JSON so the trait stays object safe.
+//!
+//! pgmq can either run in its own Postgres instance, reached through
+//! [`PgmqQueue`], or as an extension in the main database, reached through
+//! [`PgmqExtensionQueue`]. The second lets a message be sent in the same
+//! transaction as the change that causes it, see [`send_with`].
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use pgmq::PGMQueue;
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, DbErr, Statement};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueMessage {
+    pub msg_id: i64,
+    pub enqueued_at: DateTime<Utc>,
+    /// How many times the message has been read, including this one.
+    pub read_ct: i32,
+    pub message: serde_json::Value,
+}
+
+#[async_trait]
+pub trait MessageQueue: std::fmt::Debug + Send + Sync {
+    async fn create(&self, queue_name: &str) -> anyhow::Result<()>;
+    async fn send(&self, queue_name: &str, message: &serde_json::Value) -> anyhow::Result<i64>;
+    /// Sends a message that stays invisible to readers for `delay` seconds.
+    async fn send_delay(
+        &self,
+        queue_name: &str,
+        message: &serde_json::Value,
+        delay: u64,
+    ) -> anyhow::Result<i64>;
+    /// Reads the next visible message and hides it from other readers for
+    /// `visibility_timeout` seconds, after which it is redelivered unless it
+    /// has been archived.
+    async fn read(
+        &self,
+        queue_name: &str,
+        visibility_timeout: i32,
+    ) -> anyhow::Result<Option<QueueMessage>>;
+    async fn archive(&self, queue_name: &str, msg_id: i64) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct PgmqQueue {
+    queue: PGMQueue,
+}
+
+impl PgmqQueue {
+    pub async fn new(database_url: String) -> anyhow::Result<Self> {
+        Ok(Self {
+            queue: PGMQueue::new(database_url).await?,
+        })
+    }
+}
+
+#[async_trait]
+impl MessageQueue for PgmqQueue {
+    async fn create(&self, queue_name: &str) -> anyhow::Result<()> {
+        self.queue.create(queue_name).await?;
+        Ok(())
+    }
+
+    async fn send(&self, queue_name: &str, message: &serde_json::Value) -> anyhow::Result<i64> {
+        Ok(self.queue.send(queue_name, message).await?)
+    }
+
+    async fn send_delay(
+        &self,
+        queue_name: &str,
+        message: &serde_json::Value,
+        delay: u64,
+    ) -> anyhow::Result<i64> {
+        Ok(self.queue.send_delay(queue_name, message, delay).await?)
+    }
+
+    async fn read(
+        &self,
+        queue_name: &str,
+        visibility_timeout: i32,
+    ) -> anyhow::Result<Option<QueueMessage>> {
+        let message = self
+            .queue
+            .read::<serde_json::Value>(queue_name, Some(visibility_timeout))
+            .await?;
+        Ok(message.map(|message| QueueMessage {
+            msg_id: message.msg_id,
+            enqueued_at: message.enqueued_at,
+            read_ct: message.read_ct,
+            message: message.message,
+        }))
+    }
+
+    async fn archive(&self, queue_name: &str, msg_id: i64) -> anyhow::Result<()> {
+        self.queue.archive(queue_name, msg_id).await?;
+        Ok(())
+    }
+}
+
+/// Sends a message through the pgmq extension on `db`, which can be a
+/// transaction, so the message is only visible once it commits.
+pub async fn send_with<C: ConnectionTrait>(
+    db: &C,
+    queue_name: &str,
+    message: &serde_json::Value,
+    delay: u64,
+) -> Result<i64, DbErr> {
+    let row = db
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT pgmq.send($1, $2, $3) AS msg_id",
+            [
+                queue_name.into(),
+                message.clone().into(),
+                (delay as i32).into(),
+            ],
+        ))
+        .await?
+        .ok_or_else(|| DbErr::Custom(format!("pgmq.send returned nothing for {queue_name}")))?;
+    row.try_get("", "msg_id")
+}
+
+/// pgmq installed as an extension in the main database.
+#[derive(Debug, Clone)]
+pub struct PgmqExtensionQueue {
+    db: DatabaseConnection,
+}
+
+impl PgmqExtensionQueue {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl MessageQueue for PgmqExtensionQueue {
+    async fn create(&self, queue_name: &str) -> anyhow::Result<()> {
+        self.db
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                "SELECT pgmq.create($1)",
+                [queue_name.into()],
+            ))
+            .await?;
+        Ok(())
+    }
+
+    async fn send(&self, queue_name: &str, message: &serde_json::Value) -> anyhow::Result<i64> {
+        Ok(send_with(&self.db, queue_name, message, 0).await?)
+    }
+
+    async fn send_delay(
+        &self,
+        queue_name: &str,
+        message: &serde_json::Value,
+        delay: u64,
+    ) -> anyhow::Result<i64> {
+        Ok(send_with(&self.db, queue_name, message, delay).await?)
+    }
+
+    async fn read(
+        &self,
+        queue_name: &str,
+        visibility_timeout: i32,
+    ) -> anyhow::Result<Option<QueueMessage>> {
+        let row = self
+            .db
+            .query_one(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                "SELECT msg_id, read_ct, enqueued_at, message FROM pgmq.read($1, $2, 1)",
+                [queue_name.into(), visibility_timeout.into()],
+            ))
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let enqueued_at: DateTimeWithTimeZone = row.try_get("", "enqueued_at")?;
+        Ok(Some(QueueMessage {
+            msg_id: row.try_get("", "msg_id")?,
+            enqueued_at: enqueued_at.with_timezone(&Utc),
+            read_ct: row.try_get("", "read_ct")?,
+            message: row.try_get("", "message")?,
+        }))
+    }
+
+    async fn archive(&self, queue_name: &str, msg_id: i64) -> anyhow::Result<()> {
+        self.db
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                "SELECT pgmq.archive($1, $2)",
+                [queue_name.into(), msg_id.into()],
+            ))
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredMessage {
+    message: QueueMessage,
+    visible_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct InMemoryQueues {
+    next_id: i64,
+    queues: HashMap<String, Vec<StoredMessage>>,
+    archived: HashMap<String, Vec<QueueMessage>>,
+}
+
+/// A queue kept in memory with the same visibility rules as pgmq, for tests.
+#[derive(Debug, Default)]
+pub struct InMemoryQueue {
+    state: Mutex<InMemoryQueues>,
+}
+
+impl InMemoryQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Messages still on the queue, visible or not, oldest first.
+    pub fn pending(&self, queue_name: &str) -> Vec<QueueMessage> {
+        let state = self.state.lock().unwrap();
+        state
+            .queues
+            .get(queue_name)
+            .map(|messages| {
+                messages
+                    .iter()
+                    .map(|stored| stored.message.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn archived(&self, queue_name: &str) -> Vec<QueueMessage> {
+        let state = self.state.lock().unwrap();
+        state.archived.get(queue_name).cloned().unwrap_or_default()
+    }
+
+    fn push(
+        &self,
+        queue_name: &str,
+        message: &serde_json::Value,
+        delay: u64,
+    ) -> anyhow::Result<i64> {
+        let mut state = self.state.lock().unwrap();
+        if !state.queues.contains_key(queue_name) {
+            return Err(anyhow::anyhow!("Queue {queue_name} does not exist"));
+        }
+        state.next_id += 1;
+        let msg_id = state.next_id;
+        let now = Utc::now();
+        state
+            .queues
+            .get_mut(queue_name)
+            .expect("checked above")
+            .push(StoredMessage {
+                message: QueueMessage {
+                    msg_id,
+                    enqueued_at: now,
+                    read_ct: 0,
+                    message: message.clone(),
+                },
+                visible_at: now + Duration::seconds(delay as i64),
+            });
+        Ok(msg_id)
+    }
+}
+
+#[async_trait]
+impl MessageQueue for InMemoryQueue {
+    async fn create(&self, queue_name: &str) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.queues.entry(queue_name.to_string()).or_default();
+        state.archived.entry(queue_name.to_string()).or_default();
+        Ok(())
+    }
+
+    async fn send(&self, queue_name: &str, message: &serde_json::Value) -> anyhow::Result<i64> {
+        self.push(queue_name, message, 0)
+    }
+
+    async fn send_delay(
+        &self,
+        queue_name: &str,
+        message: &serde_json::Value,
+        delay: u64,
+    ) -> anyhow::Result<i64> {
+        self.push(queue_name, message, delay)
+    }
+
+    async fn read(
+        &self,
+        queue_name: &str,
+        visibility_timeout: i32,
+    ) -> anyhow::Result<Option<QueueMessage>> {
+        let mut state = self.state.lock().unwrap();
+        let messages = state
+            .queues
+            .get_mut(queue_name)
+            .ok_or_else(|| anyhow::anyhow!("Queue {queue_name} does not exist"))?;
+        let now = Utc::now();
+        let Some(stored) = messages.iter_mut().find(|stored| stored.visible_at <= now) else {
+            return Ok(None);
+        };
+        stored.message.read_ct += 1;
+        stored.visible_at = now + Duration::seconds(visibility_timeout as i64);
+        Ok(Some(stored.message.clone()))
+    }
+
+    async fn archive(&self, queue_name: &str, msg_id: i64) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let messages = state
+            .queues
+            .get_mut(queue_name)
+            .ok_or_else(|| anyhow::anyhow!("Queue {queue_name} does not exist"))?;
+        let Some(position) = messages
+            .iter()
+            .position(|stored| stored.message.msg_id == msg_id)
+        else {
+            return Ok(());
+        };
+        let archived = messages.remove(position).message;
+        state
+            .archived
+            .entry(queue_name.to_string())
+            .or_default()
+            .push(archived);
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+//! Bookkeeping for the reconciler, which compares the database with the
+//! storage backend. Each run is saved to `reconciliation_run` so the server
+//! can report the latest findings, and so a finding is only acted on once it
+//! has shown up in two runs in a row. That gives the generator time to save
+//! a row for an object it has just uploaded, and the queue time to deliver a
+//! message that is still in flight.
+
+use std::collections::BTreeSet;
+
+use sea_orm::prelude::{DateTimeWithTimeZone, Json};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbBackend, DbErr, EntityTrait,
+    FromQueryResult, QueryFilter, QueryOrder, QuerySelect, Set, Statement,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::entity::generated_image::{Column as GeneratedImageColumn, Entity as GeneratedImage};
+use crate::entity::image_derivative::{Column as ImageDerivativeColumn, Entity as ImageDerivative};
+use crate::entity::inspiration_image::{
+    Column as InspirationImageColumn, Entity as InspirationImage,
+};
+use crate::entity::reconciliation_run::{
+    ActiveModel as ReconciliationRunActiveModel, Column as ReconciliationRunColumn,
+    Entity as ReconciliationRun, Model as ReconciliationRunModel,
+};
+use crate::Lane;
+
+/// A generated image whose storage key points at an object that isn't there.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissingObject {
+    pub generated_image_id: i32,
+    pub storage_key: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    /// Inspiration images that were never generated and have no generate
+    /// message waiting in the outbox.
+    pub missing_generations: Vec<i32>,
+    pub missing_objects: Vec<MissingObject>,
+    /// Storage keys under a managed prefix that no row refers to.
+    pub orphaned_objects: Vec<String>,
+    /// Inspiration images re-enqueued, or that would have been in a dry run.
+    pub requeued: Vec<i32>,
+    /// Objects deleted, or that would have been in a dry run.
+    pub deleted: Vec<String>,
+}
+
+impl ReconciliationReport {
+    /// The findings saved for a run. Actions aren't saved, only their counts.
+    pub fn from_run(run: &ReconciliationRunModel) -> Result<Self, DbErr> {
+        Ok(Self {
+            missing_generations: from_json(&run.missing_generations)?,
+            missing_objects: from_json(&run.missing_objects)?,
+            orphaned_objects: from_json(&run.orphaned_objects)?,
+            ..Default::default()
+        })
+    }
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(value: &Json) -> Result<T, DbErr> {
+    serde_json::from_value(value.clone()).map_err(|e| DbErr::Custom(e.to_string()))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Json, DbErr> {
+    serde_json::to_value(value).map_err(|e| DbErr::Custom(e.to_string()))
+}
+
+#[derive(Debug, FromQueryResult)]
+struct InspirationImageId {
+    id: i32,
+}
+
+/// Inspiration images without a generated image that haven't been rejected
+/// or failed, and aren't already waiting to be sent from the outbox.
+pub async fn find_missing_generations(db: &DatabaseConnection) -> Result<Vec<i32>, DbErr> {
+    let rows = InspirationImageId::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT inspiration_image.id
+        FROM inspiration_image
+        WHERE inspiration_image.generation_status IS NULL
+            AND NOT EXISTS (
+                SELECT 1 FROM generated_image
+                WHERE generated_image.inspiration_image_id = inspiration_image.id
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM outbox_message
+                WHERE outbox_message.sent_at IS NULL
+                    AND outbox_message.queue_name IN ($1, $2)
+                    AND coalesce(
+                        outbox_message.payload->'payload'->>'inspiration_image_id',
+                        outbox_message.payload->>'inspiration_image_id'
+                    ) = inspiration_image.id::text
+            )
+        ORDER BY inspiration_image.id"#,
+        [
+            Lane::Priority.queue_name().into(),
+            Lane::Batch.queue_name().into(),
+        ],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
+
+/// Every storage key referenced by a generated image, archived inspiration
+/// image or derivative.
+pub async fn referenced_storage_keys(db: &DatabaseConnection) -> Result<BTreeSet<String>, DbErr> {
+    let generated: Vec<String> = GeneratedImage::find()
+        .select_only()
+        .column(GeneratedImageColumn::StorageKey)
+        .filter(GeneratedImageColumn::StorageKey.is_not_null())
+        .into_tuple()
+        .all(db)
+        .await?;
+    let archived: Vec<String> = InspirationImage::find()
+        .select_only()
+        .column(InspirationImageColumn::ArchivedKey)
+        .filter(InspirationImageColumn::ArchivedKey.is_not_null())
+        .into_tuple()
+        .all(db)
+        .await?;
+    let derivatives: Vec<String> = ImageDerivative::find()
+        .select_only()
+        .column(ImageDerivativeColumn::StorageKey)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    Ok(generated
+        .into_iter()
+        .chain(archived)
+        .chain(derivatives)
+        .collect())
+}
+
+/// Generated images whose storage key isn't among `stored_keys`.
+pub async fn find_missing_objects(
+    db: &DatabaseConnection,
+    stored_keys: &BTreeSet<String>,
+) -> Result<Vec<MissingObject>, DbErr> {
+    let rows: Vec<(i32, String)> = GeneratedImage::find()
+        .select_only()
+        .column(GeneratedImageColumn::Id)
+        .column(GeneratedImageColumn::StorageKey)
+        .filter(GeneratedImageColumn::StorageKey.is_not_null())
+        .order_by_asc(GeneratedImageColumn::Id)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter(|(_, storage_key)| !stored_keys.contains(storage_key))
+        .map(|(generated_image_id, storage_key)| MissingObject {
+            generated_image_id,
+            storage_key,
+        })
+        .collect())
+}
+
+/// The prefixes this app writes objects under. Keys outside them, such as
+/// generated images saved as `{image_id}_{uuid}` before storage keys were
+/// recorded, are never reported as orphans.
+pub const MANAGED_PREFIXES: [&str; 3] = ["generated/", "inspiration/", "derivatives/"];
+
+/// Stored keys under a managed prefix that no row refers to.
+pub fn orphaned_keys(stored_keys: &BTreeSet<String>, referenced: &BTreeSet<String>) -> Vec<String> {
+    stored_keys
+        .difference(referenced)
+        .filter(|key| {
+            MANAGED_PREFIXES
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+        })
+        .cloned()
+        .collect()
+}
+
+/// The findings of this run that were also found by the previous one.
+pub fn confirmed<T: PartialEq + Clone>(current: &[T], previous: &[T]) -> Vec<T> {
+    current
+        .iter()
+        .filter(|finding| previous.contains(finding))
+        .cloned()
+        .collect()
+}
+
+pub async fn latest_run(db: &DatabaseConnection) -> Result<Option<ReconciliationRunModel>, DbErr> {
+    ReconciliationRun::find()
+        .order_by_desc(ReconciliationRunColumn::Id)
+        .one(db)
+        .await
+}
+
+pub async fn save_run(
+    db: &DatabaseConnection,
+    started_at: DateTimeWithTimeZone,
+    dry_run: bool,
+    report: &ReconciliationReport,
+) -> Result<ReconciliationRunModel, DbErr> {
+    ReconciliationRunActiveModel {
+        started_at: Set(started_at),
+        dry_run: Set(dry_run),
+        missing_generations: Set(to_json(&report.missing_generations)?),
+        missing_objects: Set(to_json(&report.missing_objects)?),
+        orphaned_objects: Set(to_json(&report.orphaned_objects)?),
+        requeued: Set(report.requeued.len() as i32),
+        deleted: Set(report.deleted.len() as i32),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
@@ -0,0 +1,131 @@
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    Set,
+};
+
+use super::{fetch_page, Page};
+use crate::entity::generated_image::{
+    ActiveModel as GeneratedImageActiveModel, Column as GeneratedImageColumn,
+    Entity as GeneratedImage, Model as GeneratedImageModel,
+};
+use crate::prompt_drift;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NewGeneratedImage {
+    pub inspiration_image_id: i32,
+    pub source_url: String,
+    pub storage_key: Option<String>,
+    /// See [`crate::GenerateImageMessage::idempotency_key`].
+    pub idempotency_key: Option<String>,
+    pub prompt: String,
+    pub revised_prompt: String,
+    /// What the image was generated with.
+    pub size: Option<String>,
+    pub quality: Option<String>,
+    pub style: Option<String>,
+    /// Whether the image came from the description, a prompt override, a
+    /// caption of the inspiration image or a variation of it.
+    pub generation_source: Option<String>,
+}
+
+pub struct GeneratedImages<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> GeneratedImages<'a, C> {
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// Saves the image along with its prompt drift, when it was prompted.
+    /// When another row already has the idempotency key, for example because
+    /// a redelivered message saved it first, that row is returned instead.
+    pub async fn create(&self, image: NewGeneratedImage) -> Result<GeneratedImageModel, DbErr> {
+        let drift =
+            prompt_drift::is_prompted(&image.revised_prompt, image.generation_source.as_deref())
+                .then(|| prompt_drift::analyze(&image.prompt, &image.revised_prompt));
+        let mut generated_image = GeneratedImageActiveModel {
+            inspiration_image_id: Set(image.inspiration_image_id),
+            source_url: Set(image.source_url),
+            storage_key: Set(image.storage_key),
+            idempotency_key: Set(image.idempotency_key.clone()),
+            prompt: Set(image.prompt),
+            revised_prompt: Set(image.revised_prompt),
+            size: Set(image.size),
+            quality: Set(image.quality),
+            style: Set(image.style),
+            generation_source: Set(image.generation_source),
+            ..Default::default()
+        };
+        if let Some(drift) = drift {
+            drift.apply_to(&mut generated_image);
+        }
+
+        let Some(idempotency_key) = image.idempotency_key else {
+            return generated_image.insert(self.db).await;
+        };
+        GeneratedImage::insert(generated_image)
+            .on_conflict(
+                OnConflict::column(GeneratedImageColumn::IdempotencyKey)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(self.db)
+            .await?;
+        self.find_by_idempotency_key(&idempotency_key)
+            .await?
+            .ok_or_else(|| {
+                DbErr::RecordNotFound(format!("Generated image {idempotency_key} was not saved"))
+            })
+    }
+
+    pub async fn find(&self, id: i32) -> Result<Option<GeneratedImageModel>, DbErr> {
+        GeneratedImage::find_by_id(id).one(self.db).await
+    }
+
+    pub async fn find_by_idempotency_key(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<Option<GeneratedImageModel>, DbErr> {
+        GeneratedImage::find()
+            .filter(GeneratedImageColumn::IdempotencyKey.eq(idempotency_key))
+            .one(self.db)
+            .await
+    }
+
+    pub async fn first(&self) -> Result<Option<GeneratedImageModel>, DbErr> {
+        GeneratedImage::find()
+            .order_by_asc(GeneratedImageColumn::Id)
+            .one(self.db)
+            .await
+    }
+
+    /// The image after `id`, whether or not `id` itself exists.
+    pub async fn next(&self, id: i32) -> Result<Option<GeneratedImageModel>, DbErr> {
+        GeneratedImage::find()
+            .filter(GeneratedImageColumn::Id.gt(id))
+            .order_by_asc(GeneratedImageColumn::Id)
+            .one(self.db)
+            .await
+    }
+
+    /// The image before `id`, whether or not `id` itself exists.
+    pub async fn previous(&self, id: i32) -> Result<Option<GeneratedImageModel>, DbErr> {
+        GeneratedImage::find()
+            .filter(GeneratedImageColumn::Id.lt(id))
+            .order_by_desc(GeneratedImageColumn::Id)
+            .one(self.db)
+            .await
+    }
+
+    /// Oldest first.
+    pub async fn paginate(
+        &self,
+        page: u64,
+        per_page: u64,
+    ) -> Result<Page<GeneratedImageModel>, DbErr> {
+        let select = GeneratedImage::find().order_by_asc(GeneratedImageColumn::Id);
+        fetch_page(select, self.db, page, per_page).await
+    }
+}
@@ -0,0 +1,97 @@
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder, Set};
+
+use crate::entity::image_derivative::{
+    ActiveModel as ImageDerivativeActiveModel, Column as ImageDerivativeColumn,
+    Entity as ImageDerivative, Model as ImageDerivativeModel,
+};
+use crate::entity::sea_orm_active_enums::ImageKind;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewImageDerivative {
+    pub image_kind: ImageKind,
+    pub image_id: i32,
+    pub format: String,
+    pub mime_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub storage_key: String,
+    pub url: String,
+}
+
+pub struct ImageDerivatives<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> ImageDerivatives<'a, C> {
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// Saves the derivative, replacing the row for the same image, width and
+    /// format when post-processing runs again for a redelivered message.
+    pub async fn upsert(
+        &self,
+        derivative: NewImageDerivative,
+    ) -> Result<ImageDerivativeModel, DbErr> {
+        ImageDerivative::insert(ImageDerivativeActiveModel {
+            image_kind: Set(derivative.image_kind),
+            image_id: Set(derivative.image_id),
+            format: Set(derivative.format),
+            mime_type: Set(derivative.mime_type),
+            width: Set(derivative.width),
+            height: Set(derivative.height),
+            storage_key: Set(derivative.storage_key),
+            url: Set(derivative.url),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                ImageDerivativeColumn::ImageKind,
+                ImageDerivativeColumn::ImageId,
+                ImageDerivativeColumn::Width,
+                ImageDerivativeColumn::Format,
+            ])
+            .update_columns([
+                ImageDerivativeColumn::MimeType,
+                ImageDerivativeColumn::Height,
+                ImageDerivativeColumn::StorageKey,
+                ImageDerivativeColumn::Url,
+            ])
+            .to_owned(),
+        )
+        .exec_with_returning(self.db)
+        .await
+    }
+
+    pub async fn find(
+        &self,
+        image_kind: ImageKind,
+        image_id: i32,
+        width: i32,
+        format: &str,
+    ) -> Result<Option<ImageDerivativeModel>, DbErr> {
+        ImageDerivative::find()
+            .filter(ImageDerivativeColumn::ImageKind.eq(image_kind))
+            .filter(ImageDerivativeColumn::ImageId.eq(image_id))
+            .filter(ImageDerivativeColumn::Width.eq(width))
+            .filter(ImageDerivativeColumn::Format.eq(format))
+            .one(self.db)
+            .await
+    }
+
+    /// Every derivative of an image, narrowest first.
+    pub async fn for_image(
+        &self,
+        image_kind: ImageKind,
+        image_id: i32,
+    ) -> Result<Vec<ImageDerivativeModel>, DbErr> {
+        ImageDerivative::find()
+            .filter(ImageDerivativeColumn::ImageKind.eq(image_kind))
+            .filter(ImageDerivativeColumn::ImageId.eq(image_id))
+            .order_by_asc(ImageDerivativeColumn::Width)
+            .order_by_asc(ImageDerivativeColumn::Format)
+            .all(self.db)
+            .await
+    }
+}
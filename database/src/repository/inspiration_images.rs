@@ -0,0 +1,105 @@
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    Set,
+};
+
+use super::{fetch_page, Page};
+use crate::entity::inspiration_image::{
+    ActiveModel as InspirationImageActiveModel, Column as InspirationImageColumn,
+    Entity as InspirationImage, Model as InspirationImageModel,
+};
+use crate::entity::sea_orm_active_enums::GenerationStatus;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NewInspirationImage {
+    pub source_id: String,
+    pub source_url: String,
+    pub description: Option<String>,
+    pub photographer_name: Option<String>,
+    pub photographer_url: Option<String>,
+    /// The upstream image's dimensions, used to match its aspect ratio.
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+pub struct InspirationImages<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> InspirationImages<'a, C> {
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    pub async fn create(&self, image: NewInspirationImage) -> Result<InspirationImageModel, DbErr> {
+        InspirationImageActiveModel {
+            source_id: Set(image.source_id),
+            source_url: Set(image.source_url),
+            description: Set(image.description),
+            photographer_name: Set(image.photographer_name),
+            photographer_url: Set(image.photographer_url),
+            width: Set(image.width),
+            height: Set(image.height),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await
+    }
+
+    pub async fn find(&self, id: i32) -> Result<Option<InspirationImageModel>, DbErr> {
+        InspirationImage::find_by_id(id).one(self.db).await
+    }
+
+    pub async fn find_by_source_id(
+        &self,
+        source_id: &str,
+    ) -> Result<Option<InspirationImageModel>, DbErr> {
+        InspirationImage::find()
+            .filter(InspirationImageColumn::SourceId.eq(source_id))
+            .one(self.db)
+            .await
+    }
+
+    /// Records how the latest generation for the image went.
+    pub async fn set_generation_status(
+        &self,
+        id: i32,
+        status: GenerationStatus,
+        error: Option<String>,
+    ) -> Result<InspirationImageModel, DbErr> {
+        InspirationImageActiveModel {
+            id: Set(id),
+            generation_status: Set(Some(status)),
+            generation_error: Set(error),
+            ..Default::default()
+        }
+        .update(self.db)
+        .await
+    }
+
+    /// Saves a vision caption so that a redelivered message reuses it instead
+    /// of paying for another.
+    pub async fn set_caption(
+        &self,
+        id: i32,
+        caption: String,
+    ) -> Result<InspirationImageModel, DbErr> {
+        InspirationImageActiveModel {
+            id: Set(id),
+            caption: Set(Some(caption)),
+            ..Default::default()
+        }
+        .update(self.db)
+        .await
+    }
+
+    /// Oldest first.
+    pub async fn paginate(
+        &self,
+        page: u64,
+        per_page: u64,
+    ) -> Result<Page<InspirationImageModel>, DbErr> {
+        let select = InspirationImage::find().order_by_asc(InspirationImageColumn::Id);
+        fetch_page(select, self.db, page, per_page).await
+    }
+}
@@ -0,0 +1,56 @@
+//! Typed access to images and pairs, shared by the services so queries live
+//! in one place. Every repository borrows a connection or a transaction.
+
+mod generated_images;
+mod image_derivatives;
+mod inspiration_images;
+mod pairs;
+
+pub use generated_images::{GeneratedImages, NewGeneratedImage};
+pub use image_derivatives::{ImageDerivatives, NewImageDerivative};
+pub use inspiration_images::{InspirationImages, NewInspirationImage};
+pub use pairs::{Pair, PairSearchHit, Pairs, HIGHLIGHT_START, HIGHLIGHT_STOP};
+
+use sea_orm::{ConnectionTrait, DbErr, EntityTrait, PaginatorTrait, Select};
+
+/// One page of results. Pages are numbered from 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: u64,
+    pub per_page: u64,
+    pub total_items: u64,
+    pub total_pages: u64,
+}
+
+impl<T> Page<T> {
+    pub fn has_next(&self) -> bool {
+        self.page + 1 < self.total_pages
+    }
+
+    pub fn has_previous(&self) -> bool {
+        self.page > 0
+    }
+}
+
+async fn fetch_page<E, C>(
+    select: Select<E>,
+    db: &C,
+    page: u64,
+    per_page: u64,
+) -> Result<Page<E::Model>, DbErr>
+where
+    E: EntityTrait,
+    E::Model: Sync,
+    C: ConnectionTrait,
+{
+    let paginator = select.paginate(db, per_page.max(1));
+    let totals = paginator.num_items_and_pages().await?;
+    Ok(Page {
+        items: paginator.fetch_page(page).await?,
+        page,
+        per_page: per_page.max(1),
+        total_items: totals.number_of_items,
+        total_pages: totals.number_of_pages,
+    })
+}
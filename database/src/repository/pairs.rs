@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DbBackend, DbErr, EntityTrait, FromQueryResult, QueryFilter,
+    Statement,
+};
+use serde::Serialize;
+
+use super::{GeneratedImages, Page};
+use crate::entity::generated_image::Model as GeneratedImageModel;
+use crate::entity::inspiration_image::{
+    Column as InspirationImageColumn, Entity as InspirationImage, Model as InspirationImageModel,
+};
+
+// ts_headline returns the original text, so matches are wrapped in markers
+// that callers swap for markup once the text has been escaped.
+pub const HIGHLIGHT_START: &str = "\u{2}";
+pub const HIGHLIGHT_STOP: &str = "\u{3}";
+
+/// A generated image and the inspiration image it was generated from. Pairs
+/// are identified by the generated image's id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pair {
+    pub generated_image: GeneratedImageModel,
+    pub inspiration_image: InspirationImageModel,
+}
+
+#[derive(Debug, Clone, PartialEq, FromQueryResult, Serialize)]
+pub struct PairSearchHit {
+    pub generated_image_id: i32,
+    pub inspiration_image_id: i32,
+    /// The description with matches between [`HIGHLIGHT_START`] and
+    /// [`HIGHLIGHT_STOP`].
+    pub description: String,
+    /// The revised prompt with matches between [`HIGHLIGHT_START`] and
+    /// [`HIGHLIGHT_STOP`].
+    pub revised_prompt: String,
+    pub rank: f32,
+}
+
+pub struct Pairs<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> Pairs<'a, C> {
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    fn generated_images(&self) -> GeneratedImages<'a, C> {
+        GeneratedImages::new(self.db)
+    }
+
+    async fn with_inspiration_image(
+        &self,
+        generated_image: Option<GeneratedImageModel>,
+    ) -> Result<Option<Pair>, DbErr> {
+        let Some(generated_image) = generated_image else {
+            return Ok(None);
+        };
+        let inspiration_image = InspirationImage::find_by_id(generated_image.inspiration_image_id)
+            .one(self.db)
+            .await?;
+        Ok(inspiration_image.map(|inspiration_image| Pair {
+            generated_image,
+            inspiration_image,
+        }))
+    }
+
+    pub async fn find(&self, generated_image_id: i32) -> Result<Option<Pair>, DbErr> {
+        let generated_image = self.generated_images().find(generated_image_id).await?;
+        self.with_inspiration_image(generated_image).await
+    }
+
+    pub async fn first(&self) -> Result<Option<Pair>, DbErr> {
+        let generated_image = self.generated_images().first().await?;
+        self.with_inspiration_image(generated_image).await
+    }
+
+    pub async fn next(&self, generated_image_id: i32) -> Result<Option<Pair>, DbErr> {
+        let generated_image = self.generated_images().next(generated_image_id).await?;
+        self.with_inspiration_image(generated_image).await
+    }
+
+    pub async fn previous(&self, generated_image_id: i32) -> Result<Option<Pair>, DbErr> {
+        let generated_image = self.generated_images().previous(generated_image_id).await?;
+        self.with_inspiration_image(generated_image).await
+    }
+
+    /// Oldest generated image first.
+    pub async fn paginate(&self, page: u64, per_page: u64) -> Result<Page<Pair>, DbErr> {
+        let generated_images = self.generated_images().paginate(page, per_page).await?;
+        let inspiration_image_ids: Vec<i32> = generated_images
+            .items
+            .iter()
+            .map(|image| image.inspiration_image_id)
+            .collect();
+        let inspiration_images: HashMap<i32, InspirationImageModel> = InspirationImage::find()
+            .filter(InspirationImageColumn::Id.is_in(inspiration_image_ids))
+            .all(self.db)
+            .await?
+            .into_iter()
+            .map(|image| (image.id, image))
+            .collect();
+
+        // Regenerations share an inspiration image, so each is cloned.
+        let items = generated_images
+            .items
+            .into_iter()
+            .filter_map(|generated_image| {
+                let inspiration_image = inspiration_images
+                    .get(&generated_image.inspiration_image_id)?
+                    .clone();
+                Some(Pair {
+                    generated_image,
+                    inspiration_image,
+                })
+            })
+            .collect();
+        Ok(Page {
+            items,
+            page: generated_images.page,
+            per_page: generated_images.per_page,
+            total_items: generated_images.total_items,
+            total_pages: generated_images.total_pages,
+        })
+    }
+
+    /// Full-text search over inspiration descriptions, prompts and revised
+    /// prompts, best match first.
+    pub async fn search(&self, query: &str, limit: u64) -> Result<Vec<PairSearchHit>, DbErr> {
+        let headline_options =
+            format!("StartSel={HIGHLIGHT_START}, StopSel={HIGHLIGHT_STOP}, HighlightAll=true");
+        PairSearchHit::find_by_statement(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"SELECT
+                generated_image.id AS generated_image_id,
+                inspiration_image.id AS inspiration_image_id,
+                ts_headline('english', coalesce(inspiration_image.description, ''), query, $2) AS description,
+                ts_headline('english', generated_image.revised_prompt, query, $2) AS revised_prompt,
+                (ts_rank(generated_image.search_vector, query) +
+                    ts_rank(inspiration_image.search_vector, query))::real AS rank
+            FROM generated_image
+            JOIN inspiration_image ON inspiration_image.id = generated_image.inspiration_image_id,
+                websearch_to_tsquery('english', $1) query
+            WHERE generated_image.search_vector @@ query OR inspiration_image.search_vector @@ query
+            ORDER BY rank DESC, generated_image.id
+            LIMIT $3"#,
+            [query.into(), headline_options.into(), (limit as i64).into()],
+        ))
+        .all(self.db)
+        .await
+    }
+}
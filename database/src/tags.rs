@@ -0,0 +1,231 @@
+use std::collections::BTreeSet;
+
+use sea_orm::sea_query::{Alias, Expr, Func, OnConflict};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+
+use crate::entity::image_tag::{
+    ActiveModel as ImageTagActiveModel, Column as ImageTagColumn, Entity as ImageTag,
+};
+use crate::entity::sea_orm_active_enums::{ImageKind, TagSource};
+use crate::entity::tag::{
+    ActiveModel as TagActiveModel, Column as TagColumn, Entity as Tag, Model as TagModel,
+};
+
+const MAX_TAG_LENGTH: usize = 50;
+
+/// Lowercases a tag and collapses whitespace so "Golden  Hour" and
+/// "golden hour" are the same tag. Returns `None` for tags that are empty or
+/// too long to be useful.
+pub fn normalize_tag(name: &str) -> Option<String> {
+    let tag = normalize_words(name.split_whitespace()).join(" ");
+
+    if tag.is_empty() || tag.len() > MAX_TAG_LENGTH {
+        return None;
+    }
+    Some(tag)
+}
+
+fn normalize_words<'a>(words: impl Iterator<Item = &'a str>) -> Vec<String> {
+    words
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || *c == '-')
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// The words of a prompt, normalized like tags but split on dashes too so
+/// "black-and-white" matches "black and white".
+fn prompt_words(prompt: &str) -> Vec<String> {
+    normalize_words(prompt.split(|c: char| c.is_whitespace() || c == '-'))
+}
+
+/// Every run of consecutive prompt words short enough to be a tag, along
+/// with its singular when it ends in "s". Tags are compared with dashes
+/// replaced by spaces, so these are too.
+pub fn prompt_tag_candidates(prompt: &str) -> BTreeSet<String> {
+    let words = prompt_words(prompt);
+    let mut candidates = BTreeSet::new();
+    for start in 0..words.len() {
+        let mut candidate = String::new();
+        for word in &words[start..] {
+            if !candidate.is_empty() {
+                candidate.push(' ');
+            }
+            candidate.push_str(word);
+            if candidate.len() > MAX_TAG_LENGTH {
+                break;
+            }
+            if let Some(singular) = candidate.strip_suffix('s') {
+                candidates.insert(singular.to_string());
+            }
+            candidates.insert(candidate.clone());
+        }
+    }
+    candidates
+}
+
+/// Tags from `vocabulary` that appear in the prompt, either as written or
+/// with a trailing "s". Prompts are free text, so only tags that already
+/// exist are extracted rather than inventing a tag for every word.
+pub fn extract_prompt_tags(prompt: &str, vocabulary: &[String]) -> Vec<String> {
+    let words = prompt_words(prompt);
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let haystack = format!(" {} ", words.join(" "));
+
+    let mut tags: Vec<String> = vocabulary
+        .iter()
+        .filter(|tag| {
+            let tag = tag.replace('-', " ");
+            haystack.contains(&format!(" {tag} ")) || haystack.contains(&format!(" {tag}s "))
+        })
+        .cloned()
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+pub async fn find_or_create_tag(db: &DatabaseConnection, name: &str) -> Result<TagModel, DbErr> {
+    let name =
+        normalize_tag(name).ok_or_else(|| DbErr::Custom(format!("Invalid tag \"{name}\"")))?;
+
+    Tag::insert(TagActiveModel {
+        name: Set(name.clone()),
+        ..Default::default()
+    })
+    .on_conflict(OnConflict::column(TagColumn::Name).do_nothing().to_owned())
+    .exec_without_returning(db)
+    .await?;
+
+    Tag::find()
+        .filter(TagColumn::Name.eq(name))
+        .one(db)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound("Tag was not created".to_string()))
+}
+
+/// Adds a tag to an image. Tagging an image twice with the same tag keeps the
+/// original source.
+pub async fn tag_image(
+    db: &DatabaseConnection,
+    image_kind: ImageKind,
+    image_id: i32,
+    name: &str,
+    source: TagSource,
+) -> Result<TagModel, DbErr> {
+    let tag = find_or_create_tag(db, name).await?;
+
+    ImageTag::insert(ImageTagActiveModel {
+        tag_id: Set(tag.id),
+        image_kind: Set(image_kind),
+        image_id: Set(image_id),
+        source: Set(source),
+        ..Default::default()
+    })
+    .on_conflict(
+        OnConflict::columns([
+            ImageTagColumn::TagId,
+            ImageTagColumn::ImageKind,
+            ImageTagColumn::ImageId,
+        ])
+        .do_nothing()
+        .to_owned(),
+    )
+    .exec_without_returning(db)
+    .await?;
+
+    Ok(tag)
+}
+
+/// Returns whether the image had the tag.
+pub async fn untag_image(
+    db: &DatabaseConnection,
+    image_kind: ImageKind,
+    image_id: i32,
+    name: &str,
+) -> Result<bool, DbErr> {
+    let Some(name) = normalize_tag(name) else {
+        return Ok(false);
+    };
+    let Some(tag) = Tag::find().filter(TagColumn::Name.eq(name)).one(db).await? else {
+        return Ok(false);
+    };
+
+    let result = ImageTag::delete_many()
+        .filter(ImageTagColumn::TagId.eq(tag.id))
+        .filter(ImageTagColumn::ImageKind.eq(image_kind))
+        .filter(ImageTagColumn::ImageId.eq(image_id))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+pub async fn tags_for_image(
+    db: &DatabaseConnection,
+    image_kind: ImageKind,
+    image_id: i32,
+) -> Result<Vec<TagModel>, DbErr> {
+    Tag::find()
+        .inner_join(ImageTag)
+        .filter(ImageTagColumn::ImageKind.eq(image_kind))
+        .filter(ImageTagColumn::ImageId.eq(image_id))
+        .order_by_asc(TagColumn::Name)
+        .all(db)
+        .await
+}
+
+/// Tags a generated image with every existing tag mentioned in its prompt or
+/// revised prompt.
+pub async fn tag_from_prompt(
+    db: &DatabaseConnection,
+    generated_image_id: i32,
+    prompt: &str,
+    revised_prompt: &str,
+) -> Result<Vec<String>, DbErr> {
+    // Only the tags the prompts could mention, not the whole vocabulary.
+    let mut candidates = prompt_tag_candidates(prompt);
+    candidates.extend(prompt_tag_candidates(revised_prompt));
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let vocabulary: Vec<String> = Tag::find()
+        .select_only()
+        .column(TagColumn::Name)
+        .filter(
+            Expr::expr(
+                Func::cust(Alias::new("replace"))
+                    .arg(Expr::col(TagColumn::Name))
+                    .arg("-")
+                    .arg(" "),
+            )
+            .is_in(candidates),
+        )
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut tags = extract_prompt_tags(prompt, &vocabulary);
+    tags.extend(extract_prompt_tags(revised_prompt, &vocabulary));
+    tags.sort();
+    tags.dedup();
+
+    for tag in &tags {
+        tag_image(
+            db,
+            ImageKind::Generated,
+            generated_image_id,
+            tag,
+            TagSource::Prompt,
+        )
+        .await?;
+    }
+    Ok(tags)
+}
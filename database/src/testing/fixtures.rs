@@ -0,0 +1,289 @@
+//! Deterministic inspiration/generated pairs with placeholder images, for
+//! tests and for seeding dev databases.
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use image::{ImageOutputFormat, Rgb, RgbImage};
+use sea_orm::{
+    ActiveModelTrait, ConnectionTrait, DatabaseConnection, DbErr, Set, TransactionTrait,
+};
+use storage::Storage;
+
+use crate::entity::generated_image::Model as GeneratedImageModel;
+use crate::entity::inspiration_image::{
+    ActiveModel as InspirationImageActiveModel, Model as InspirationImageModel,
+};
+use crate::entity::sea_orm_active_enums::{GenerationStatus, ImageKind, TagSource};
+use crate::prompt_drift::VARIATION_SOURCE;
+use crate::repository::{
+    GeneratedImages, InspirationImages, NewGeneratedImage, NewInspirationImage,
+};
+use crate::tags::tag_image;
+use crate::GenerateImageMessage;
+
+/// Seeded inspiration images use this `source_id` prefix, so they can be told
+/// apart from collected ones and seeding again adds new pairs.
+pub const SEED_SOURCE_PREFIX: &str = "seed-";
+pub const PLACEHOLDER_SIZE: u32 = 256;
+
+const SUBJECTS: [&str; 8] = [
+    "a red bicycle",
+    "an old lighthouse",
+    "a sleeping cat",
+    "a bowl of ramen",
+    "a mountain cabin",
+    "a street musician",
+    "a field of sunflowers",
+    "a vintage typewriter",
+];
+const SETTINGS: [&str; 6] = [
+    "on a rainy street",
+    "by the sea",
+    "in a quiet forest",
+    "on a crowded market",
+    "under a starry sky",
+    "in a sunlit kitchen",
+];
+const STYLES: [&str; 4] = [
+    "shot on film",
+    "in soft morning light",
+    "with long shadows",
+    "in muted colours",
+];
+const TAGS: [&str; 6] = ["nature", "city", "food", "night", "animals", "vintage"];
+
+/// splitmix64, so a given index always produces the same fixture without
+/// pulling in a random number crate.
+fn mix(mut value: u64) -> u64 {
+    value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+fn pick<'a>(options: &[&'a str], index: u32, salt: u64) -> &'a str {
+    options[(mix(index as u64 ^ (salt << 32)) % options.len() as u64) as usize]
+}
+
+pub fn synthetic_description(index: u32) -> String {
+    format!(
+        "{} {} {}",
+        pick(&SUBJECTS, index, 1),
+        pick(&SETTINGS, index, 2),
+        pick(&STYLES, index, 3)
+    )
+}
+
+/// One or two tags for the fixture at `index`.
+pub fn synthetic_tags(index: u32) -> Vec<String> {
+    let first = pick(&TAGS, index, 4);
+    let second = pick(&TAGS, index, 5);
+    if first == second || mix(index as u64 ^ (6 << 32)) & 1 == 0 {
+        vec![first.to_string()]
+    } else {
+        vec![first.to_string(), second.to_string()]
+    }
+}
+
+/// A PNG gradient whose colours depend only on `seed`.
+pub fn placeholder_image(seed: u64, width: u32, height: u32) -> anyhow::Result<Vec<u8>> {
+    let bits = mix(seed);
+    let from = [bits as u8, (bits >> 8) as u8, (bits >> 16) as u8];
+    let to = [(bits >> 24) as u8, (bits >> 32) as u8, (bits >> 40) as u8];
+    let image = RgbImage::from_fn(width, height, |x, y| {
+        let t = (x + y) as f32 / (width + height).max(2) as f32;
+        Rgb([0, 1, 2].map(|c| (from[c] as f32 * (1.0 - t) + to[c] as f32 * t) as u8))
+    });
+
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageOutputFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+pub fn new_inspiration_image(source_id: &str, description: &str) -> NewInspirationImage {
+    NewInspirationImage {
+        source_id: source_id.to_string(),
+        source_url: format!("https://example.com/{source_id}"),
+        description: Some(description.to_string()),
+        ..Default::default()
+    }
+}
+
+pub fn new_generated_image(inspiration_image_id: i32, revised_prompt: &str) -> NewGeneratedImage {
+    NewGeneratedImage {
+        inspiration_image_id,
+        source_url: format!("https://example.com/generated/{inspiration_image_id}"),
+        idempotency_key: Some(GenerateImageMessage::new(inspiration_image_id).idempotency_key()),
+        prompt: "a red bicycle".to_string(),
+        revised_prompt: revised_prompt.to_string(),
+        size: Some("1792x1024".to_string()),
+        quality: Some("hd".to_string()),
+        style: Some("natural".to_string()),
+        generation_source: Some("description".to_string()),
+        ..Default::default()
+    }
+}
+
+/// Saves an inspiration image and one generated from it through the
+/// repositories, without touching storage.
+pub async fn create_pair<C: ConnectionTrait>(
+    db: &C,
+    source_id: &str,
+    description: &str,
+    revised_prompt: &str,
+) -> Result<GeneratedImageModel, DbErr> {
+    let inspiration_image = InspirationImages::new(db)
+        .create(new_inspiration_image(source_id, description))
+        .await?;
+    GeneratedImages::new(db)
+        .create(new_generated_image(inspiration_image.id, revised_prompt))
+        .await
+}
+
+#[derive(Debug)]
+pub struct SeededPair {
+    pub inspiration_image: InspirationImageModel,
+    pub generated_image: GeneratedImageModel,
+}
+
+/// Builds an inspiration/generated pair with placeholder images. Everything
+/// is derived from the index unless overridden, so tests can rely on it.
+#[derive(Debug, Clone)]
+pub struct PairFixture {
+    index: u32,
+    description: String,
+    revised_prompt: Option<String>,
+    generation_source: String,
+    tags: Vec<String>,
+}
+
+impl PairFixture {
+    pub fn new(index: u32) -> Self {
+        Self {
+            index,
+            description: synthetic_description(index),
+            revised_prompt: None,
+            generation_source: "description".to_string(),
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Defaults to a longer version of the description, like DALL·E's.
+    pub fn revised_prompt(mut self, revised_prompt: impl Into<String>) -> Self {
+        self.revised_prompt = Some(revised_prompt.into());
+        self
+    }
+
+    /// `description` unless set, e.g. to [`VARIATION_SOURCE`] for an
+    /// unprompted pair.
+    pub fn generation_source(mut self, generation_source: impl Into<String>) -> Self {
+        self.generation_source = generation_source.into();
+        self
+    }
+
+    /// Tags the inspiration image, which tags the pair.
+    pub fn tags<T: Into<String>>(mut self, tags: impl IntoIterator<Item = T>) -> Self {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub async fn insert(
+        self,
+        db: &DatabaseConnection,
+        storage: Arc<dyn Storage>,
+    ) -> anyhow::Result<SeededPair> {
+        let source_id = format!("{SEED_SOURCE_PREFIX}{}", self.index);
+        let inspiration_key = format!("inspiration/{source_id}.png");
+        let generated_key = format!("generated/{source_id}.png");
+        storage
+            .put(
+                &inspiration_key,
+                placeholder_image(self.index as u64 * 2, PLACEHOLDER_SIZE, PLACEHOLDER_SIZE)?,
+                "image/png",
+            )
+            .await?;
+        storage
+            .put(
+                &generated_key,
+                placeholder_image(
+                    self.index as u64 * 2 + 1,
+                    PLACEHOLDER_SIZE,
+                    PLACEHOLDER_SIZE,
+                )?,
+                "image/png",
+            )
+            .await?;
+
+        let variation = self.generation_source == VARIATION_SOURCE;
+        let revised_prompt = match self.revised_prompt {
+            Some(revised_prompt) => revised_prompt,
+            // Variations aren't prompted, so DALL·E has nothing to revise.
+            None if variation => String::new(),
+            None => format!(
+                "A detailed photograph of {}, richly textured and carefully composed",
+                self.description
+            ),
+        };
+
+        let txn = db.begin().await?;
+        let inspiration_image = InspirationImages::new(&txn)
+            .create(NewInspirationImage {
+                source_id,
+                source_url: storage.url(&inspiration_key),
+                description: Some(self.description.clone()),
+                width: Some(PLACEHOLDER_SIZE as i32),
+                height: Some(PLACEHOLDER_SIZE as i32),
+                ..Default::default()
+            })
+            .await?;
+        // Archived the way the collector archives an image.
+        let mut archived: InspirationImageActiveModel = inspiration_image.into();
+        archived.archived_key = Set(Some(inspiration_key.clone()));
+        archived.archived_url = Set(Some(storage.url(&inspiration_key)));
+        archived.mime_type = Set(Some("image/png".to_string()));
+        let archived = archived.update(&txn).await?;
+        let inspiration_image = InspirationImages::new(&txn)
+            .set_generation_status(archived.id, GenerationStatus::Completed, None)
+            .await?;
+
+        let generated_image = GeneratedImages::new(&txn)
+            .create(NewGeneratedImage {
+                inspiration_image_id: inspiration_image.id,
+                source_url: storage.url(&generated_key),
+                storage_key: Some(generated_key),
+                idempotency_key: Some(
+                    GenerateImageMessage::new(inspiration_image.id).idempotency_key(),
+                ),
+                prompt: self.description.clone(),
+                revised_prompt,
+                size: Some("1024x1024".to_string()),
+                quality: Some("standard".to_string()),
+                style: (!variation).then(|| "vivid".to_string()),
+                generation_source: Some(self.generation_source.clone()),
+            })
+            .await?;
+        txn.commit().await?;
+
+        for tag in &self.tags {
+            tag_image(
+                db,
+                ImageKind::Inspiration,
+                inspiration_image.id,
+                tag,
+                TagSource::Manual,
+            )
+            .await?;
+        }
+
+        Ok(SeededPair {
+            inspiration_image,
+            generated_image,
+        })
+    }
+}
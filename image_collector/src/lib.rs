@@ -1,11 +1,30 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
 use anyhow::anyhow;
 use database::entity::inspiration_image::{
-    ActiveModel as InspirationImageActiveModel, Model as InspirationImageModel,
+    ActiveModel as InspirationImageActiveModel, Column as InspirationImageColumn,
+    Entity as InspirationImage, Model as InspirationImageModel,
+};
+use database::entity::sea_orm_active_enums::{ImageKind, TagSource};
+use database::messages::new_correlation_id;
+use database::outbox::{enqueue, publish, relay_outbox, DEFAULT_RELAY_BATCH_SIZE};
+use database::queue::MessageQueue;
+use database::reconciliation::{
+    confirmed, find_missing_generations, find_missing_objects, latest_run, orphaned_keys,
+    referenced_storage_keys, save_run, ReconciliationReport,
 };
-use reqwest::{Client, Response};
+use database::repository::{InspirationImages, NewInspirationImage};
+use database::tags::tag_image;
+use database::{GenerateImageMessage, QueueBackend, GENERATE_IMAGE_QUEUE};
+use reqwest::{header::CONTENT_TYPE, Client, Response, StatusCode};
 use sea_orm::ActiveValue::Set;
-use sea_orm::{ActiveModelTrait, DatabaseConnection};
-use tracing::{event, instrument, Level};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, TransactionTrait,
+};
+use sha2::{Digest, Sha256};
+use storage::Storage;
+use tracing::{event, instrument, Level, Span};
 
 #[derive(serde::Deserialize, Debug)]
 pub struct UnsplashImage {
@@ -13,6 +32,17 @@ pub struct UnsplashImage {
     pub urls: ImageUrls,
     pub description: Option<String>,
     pub alt_description: Option<String>,
+    pub user: Option<UnsplashUser>,
+    pub links: Option<ImageLinks>,
+    #[serde(default)]
+    pub tags: Vec<UnsplashTag>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct UnsplashTag {
+    pub title: String,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -20,10 +50,33 @@ pub struct ImageUrls {
     pub regular: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(serde::Deserialize, Debug)]
+pub struct ImageLinks {
+    pub download_location: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct UnsplashUser {
+    pub name: String,
+    pub links: UserLinks,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct UserLinks {
+    pub html: String,
+}
+
+#[derive(Clone)]
 pub struct ImageClient {
     http_client: Client,
     base_url: String,
+    access_key: Option<String>,
+}
+
+impl std::fmt::Debug for ImageClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImageClient").finish_non_exhaustive()
+    }
 }
 
 impl ImageClient {
@@ -31,13 +84,52 @@ impl ImageClient {
         Self {
             http_client: Client::new(),
             base_url,
+            access_key: None,
         }
     }
 
+    pub fn with_access_key(mut self, access_key: String) -> Self {
+        self.access_key = Some(access_key);
+        self
+    }
+
     pub async fn get_images(&self) -> anyhow::Result<Response> {
         let res = self.http_client.get(&self.base_url).send().await?;
         Ok(res)
     }
+
+    /// Unsplash's API guidelines require hitting the photo's download endpoint
+    /// whenever the photo itself is downloaded.
+    pub async fn track_download(&self, download_location: &str) -> anyhow::Result<()> {
+        let mut request = self.http_client.get(download_location);
+        if let Some(access_key) = &self.access_key {
+            request = request.header("Authorization", format!("Client-ID {access_key}"));
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn download(&self, url: &str) -> anyhow::Result<(Vec<u8>, Option<String>)> {
+        let res = self.http_client.get(url).send().await?.error_for_status()?;
+        let mime_type = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        let data = res.bytes().await?.to_vec();
+        Ok((data, mime_type))
+    }
+
+    /// Returns `Ok(false)` only when upstream says the photo is gone, so that
+    /// network errors don't mark images as removed.
+    pub async fn is_available(&self, url: &str) -> anyhow::Result<bool> {
+        let res = self.http_client.head(url).send().await?;
+        match res.status() {
+            StatusCode::NOT_FOUND | StatusCode::GONE => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(anyhow!("Unexpected status checking {url}: {status}")),
+        }
+    }
 }
 
 #[instrument]
@@ -52,26 +144,310 @@ pub async fn fetch_images(client: ImageClient) -> anyhow::Result<Vec<UnsplashIma
     Err(anyhow!("woops bad result"))
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CollectOptions {
+    /// Save images without a description or alt text, leaving the generator
+    /// to caption or vary the image itself.
+    pub accept_undescribed: bool,
+}
+
+impl CollectOptions {
+    pub fn from_env() -> Self {
+        Self {
+            accept_undescribed: std::env::var("COLLECT_UNDESCRIBED_IMAGES")
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(false),
+        }
+    }
+}
+
 #[instrument]
 pub async fn insert_image(
     db: &DatabaseConnection,
+    backend: &QueueBackend,
     image: UnsplashImage,
+    correlation_id: &str,
+    options: CollectOptions,
 ) -> anyhow::Result<InspirationImageModel> {
-    if image.description.is_none() && image.alt_description.is_none() {
+    let description = image.description.or(image.alt_description);
+    if description.is_none() && !options.accept_undescribed {
         return Err(anyhow!("Image cannot be saved without a description"));
     }
-    let description = match image.description {
-        Some(d) => d,
-        None => image.alt_description.expect("checked above for some"),
-    };
-    let inspiration_image = InspirationImageActiveModel {
-        source_id: Set(image.id),
-        source_url: Set(image.urls.regular),
-        description: Set(Some(description)),
-        ..Default::default()
+    let inspiration_image = NewInspirationImage {
+        source_id: image.id,
+        source_url: image.urls.regular,
+        description,
+        photographer_name: image.user.as_ref().map(|user| user.name.clone()),
+        photographer_url: image.user.map(|user| user.links.html),
+        width: image.width,
+        height: image.height,
     };
 
-    let result = inspiration_image.insert(db).await?;
+    // The generate message is published in the same transaction, so an image
+    // is never saved without eventually being generated.
+    let txn = db.begin().await?;
+    let result = InspirationImages::new(&txn)
+        .create(inspiration_image)
+        .await?;
+    publish(
+        &txn,
+        backend,
+        GENERATE_IMAGE_QUEUE,
+        &GenerateImageMessage::new(result.id)
+            .into_envelope()
+            .with_correlation_id(correlation_id),
+    )
+    .await?;
+    txn.commit().await?;
     println!("Saved image");
+
+    // Tags are nice to have, so a failure here shouldn't stop the image from
+    // being generated.
+    for tag in image.tags {
+        if let Err(e) = tag_image(
+            db,
+            ImageKind::Inspiration,
+            result.id,
+            &tag.title,
+            TagSource::Unsplash,
+        )
+        .await
+        {
+            event!(Level::WARN, "Error tagging image with {}: {e}", tag.title);
+        }
+    }
+
     Ok(result)
 }
+
+/// The daily collection job: fetches the latest images, saves each one with
+/// its generate message and archives a copy. A failure on one image is
+/// logged and the rest carry on. Returns how many images were saved.
+///
+/// Every message published by one run shares a correlation id, recorded on
+/// the run's span, so a generation can be traced back to the fetch.
+#[instrument(skip(storage), fields(correlation_id))]
+pub async fn collect_images(
+    db: &DatabaseConnection,
+    backend: &QueueBackend,
+    client: &ImageClient,
+    storage: Arc<dyn Storage>,
+    options: CollectOptions,
+) -> anyhow::Result<usize> {
+    let correlation_id = new_correlation_id();
+    Span::current().record("correlation_id", correlation_id.as_str());
+    let images = fetch_images(client.clone()).await?;
+
+    let mut saved = 0;
+    for image in images {
+        event!(Level::INFO, "Saving image to db");
+        let download_location = image
+            .links
+            .as_ref()
+            .map(|links| links.download_location.clone());
+        let image = match insert_image(db, backend, image, &correlation_id, options).await {
+            Ok(image) => image,
+            Err(e) => {
+                event!(Level::WARN, "Error saving image: {e}");
+                continue;
+            }
+        };
+        saved += 1;
+
+        // Archiving is best effort, the gallery falls back to the upstream
+        // url without it.
+        if let Err(e) = archive_image(db, client, storage.clone(), image, download_location).await {
+            event!(Level::WARN, "Error archiving image: {e}");
+        }
+    }
+
+    Ok(saved)
+}
+
+/// Publishes pending outbox messages, one batch at a time, until none are
+/// left or a send fails. Returns how many were published.
+#[instrument(skip(db, queue))]
+pub async fn relay_pending(
+    db: &DatabaseConnection,
+    queue: &dyn MessageQueue,
+) -> anyhow::Result<u64> {
+    let mut sent = 0;
+    loop {
+        let summary = relay_outbox(db, queue, DEFAULT_RELAY_BATCH_SIZE).await?;
+        sent += summary.sent;
+        if summary.failed > 0 || summary.sent < DEFAULT_RELAY_BATCH_SIZE {
+            return Ok(sent);
+        }
+    }
+}
+
+fn extension_for_mime_type(mime_type: &str) -> &'static str {
+    match mime_type {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/avif" => "avif",
+        _ => "bin",
+    }
+}
+
+/// Downloads the inspiration image and stores a copy so the gallery doesn't
+/// depend on the photo staying up on Unsplash.
+#[instrument(skip(storage))]
+pub async fn archive_image(
+    db: &DatabaseConnection,
+    client: &ImageClient,
+    storage: Arc<dyn Storage>,
+    image: InspirationImageModel,
+    download_location: Option<String>,
+) -> anyhow::Result<InspirationImageModel> {
+    if let Some(download_location) = download_location {
+        client.track_download(&download_location).await?;
+    }
+
+    let (data, mime_type) = client.download(&image.source_url).await?;
+    let mime_type = mime_type.unwrap_or("application/octet-stream".to_string());
+    let content_hash = hex::encode(Sha256::digest(&data));
+    let key = format!(
+        "inspiration/{}.{}",
+        image.source_id,
+        extension_for_mime_type(&mime_type)
+    );
+    storage.put(&key, data, &mime_type).await?;
+
+    let archived_url = storage.url(&key);
+    let mut inspiration_image: InspirationImageActiveModel = image.into();
+    inspiration_image.archived_key = Set(Some(key));
+    inspiration_image.archived_url = Set(Some(archived_url));
+    inspiration_image.content_hash = Set(Some(content_hash));
+    inspiration_image.mime_type = Set(Some(mime_type));
+    inspiration_image.upstream_available = Set(Some(true));
+    inspiration_image.upstream_checked_at = Set(Some(chrono::Utc::now().into()));
+
+    let result = inspiration_image.update(db).await?;
+    event!(Level::INFO, "Archived inspiration image");
+    Ok(result)
+}
+
+/// Re-checks that archived images are still available upstream. Returns the
+/// number of images that have been removed.
+#[instrument]
+pub async fn verify_upstream(db: &DatabaseConnection, client: &ImageClient) -> anyhow::Result<u64> {
+    let images = InspirationImage::find()
+        .filter(InspirationImageColumn::ArchivedKey.is_not_null())
+        .all(db)
+        .await?;
+
+    let mut unavailable = 0;
+    for image in images {
+        let available = match client.is_available(&image.source_url).await {
+            Ok(available) => available,
+            Err(e) => {
+                event!(Level::WARN, "Error checking upstream image: {e}");
+                continue;
+            }
+        };
+        if !available {
+            unavailable += 1;
+        }
+
+        let mut inspiration_image: InspirationImageActiveModel = image.into();
+        inspiration_image.upstream_available = Set(Some(available));
+        inspiration_image.upstream_checked_at = Set(Some(chrono::Utc::now().into()));
+        inspiration_image.update(db).await?;
+    }
+
+    Ok(unavailable)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReconcileOptions {
+    /// Report what would be re-enqueued or deleted without doing it.
+    pub dry_run: bool,
+    /// Re-enqueue generate messages for missing generations.
+    pub requeue: bool,
+    /// Delete objects in storage that no row refers to.
+    pub delete_orphans: bool,
+}
+
+impl ReconcileOptions {
+    pub fn from_env() -> Self {
+        let flag = |name: &str| {
+            std::env::var(name)
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(false)
+        };
+        Self {
+            dry_run: flag("RECONCILE_DRY_RUN"),
+            requeue: flag("RECONCILE_REQUEUE"),
+            delete_orphans: flag("RECONCILE_DELETE_ORPHANS"),
+        }
+    }
+}
+
+/// Looks for inspiration images that were never generated, generated images
+/// whose object is missing from storage, and objects no row refers to, then
+/// saves the findings as a `reconciliation_run`. Only findings that the
+/// previous run also saw are re-enqueued or deleted.
+#[instrument(skip(storage))]
+pub async fn reconcile(
+    db: &DatabaseConnection,
+    storage: Arc<dyn Storage>,
+    options: ReconcileOptions,
+) -> anyhow::Result<ReconciliationReport> {
+    let started_at = chrono::Utc::now();
+    let previous = match latest_run(db).await? {
+        Some(run) => ReconciliationReport::from_run(&run)?,
+        None => ReconciliationReport::default(),
+    };
+
+    let stored_keys: BTreeSet<String> = storage.list("").await?.into_iter().collect();
+    let referenced = referenced_storage_keys(db).await?;
+    let mut report = ReconciliationReport {
+        missing_generations: find_missing_generations(db).await?,
+        missing_objects: find_missing_objects(db, &stored_keys).await?,
+        orphaned_objects: orphaned_keys(&stored_keys, &referenced),
+        ..Default::default()
+    };
+
+    if options.requeue {
+        for inspiration_image_id in
+            confirmed(&report.missing_generations, &previous.missing_generations)
+        {
+            if !options.dry_run {
+                enqueue(
+                    db,
+                    GENERATE_IMAGE_QUEUE,
+                    &GenerateImageMessage::new(inspiration_image_id).into_envelope(),
+                )
+                .await?;
+            }
+            report.requeued.push(inspiration_image_id);
+        }
+    }
+
+    if options.delete_orphans {
+        for key in confirmed(&report.orphaned_objects, &previous.orphaned_objects) {
+            if !options.dry_run {
+                if let Err(e) = storage.delete(&key).await {
+                    event!(Level::WARN, "Error deleting orphaned object {key}: {e}");
+                    continue;
+                }
+            }
+            report.deleted.push(key);
+        }
+    }
+
+    save_run(db, started_at.into(), options.dry_run, &report).await?;
+    event!(
+        Level::INFO,
+        "Reconciled: {} missing generations, {} missing objects, {} orphaned objects, {} requeued, {} deleted{}",
+        report.missing_generations.len(),
+        report.missing_objects.len(),
+        report.orphaned_objects.len(),
+        report.requeued.len(),
+        report.deleted.len(),
+        if options.dry_run { " (dry run)" } else { "" }
+    );
+    Ok(report)
+}
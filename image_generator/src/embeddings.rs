@@ -0,0 +1,33 @@
+use database::embedding::{embedding_input, save_embedding};
+use database::entity::generated_image::Model as GeneratedImageModel;
+use database::prompt_drift::is_prompted;
+use sea_orm::DatabaseConnection;
+use tracing::instrument;
+
+use crate::open_ai::{OpenAiClient, EMBEDDING_MODEL};
+
+/// Embeds the description and revised prompt of a generated image and stores
+/// the vector for similar image lookups. Images without a revised prompt,
+/// like variations, aren't embedded and this returns `false`.
+#[instrument(skip_all, fields(generated_image_id = generated_image.id))]
+pub async fn embed_generated_image(
+    db: &DatabaseConnection,
+    client: &OpenAiClient,
+    generated_image: &GeneratedImageModel,
+    description: Option<&str>,
+) -> anyhow::Result<bool> {
+    if !is_prompted(
+        &generated_image.revised_prompt,
+        generated_image.generation_source.as_deref(),
+    ) {
+        return Ok(false);
+    }
+    let embedding = client
+        .create_embedding(&embedding_input(
+            description,
+            &generated_image.revised_prompt,
+        ))
+        .await?;
+    save_embedding(db, generated_image.id, EMBEDDING_MODEL, &embedding).await?;
+    Ok(true)
+}
@@ -0,0 +1,80 @@
+use database::Lane;
+
+#[derive(Debug)]
+struct WeightedLane {
+    queue_name: String,
+    weight: i64,
+    current: i64,
+}
+
+/// Decides which queue to read next using smooth weighted round robin, so
+/// with weights 4 and 1 the busier lane gets four of every five reads while
+/// both have work, without starving the other.
+#[derive(Debug)]
+pub struct WeightedLanes {
+    lanes: Vec<WeightedLane>,
+}
+
+impl WeightedLanes {
+    pub fn new(lanes: Vec<(String, u32)>) -> Self {
+        Self {
+            lanes: lanes
+                .into_iter()
+                .map(|(queue_name, weight)| WeightedLane {
+                    queue_name,
+                    weight: weight.max(1) as i64,
+                    current: 0,
+                })
+                .collect(),
+        }
+    }
+
+    /// Every generate lane, with weights from `PRIORITY_LANE_WEIGHT` and
+    /// `BATCH_LANE_WEIGHT` when they are set.
+    pub fn from_env() -> Self {
+        let weight = |lane: Lane, name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(lane.default_weight())
+        };
+        Self::new(vec![
+            (
+                Lane::Priority.queue_name().to_string(),
+                weight(Lane::Priority, "PRIORITY_LANE_WEIGHT"),
+            ),
+            (
+                Lane::Batch.queue_name().to_string(),
+                weight(Lane::Batch, "BATCH_LANE_WEIGHT"),
+            ),
+        ])
+    }
+
+    /// The order to try the lanes in for the next read: the lane whose turn
+    /// it is, then the others by weight in case it is empty.
+    pub fn next_order(&mut self) -> Vec<String> {
+        let total: i64 = self.lanes.iter().map(|lane| lane.weight).sum();
+        for lane in &mut self.lanes {
+            lane.current += lane.weight;
+        }
+        let Some(selected) =
+            (0..self.lanes.len()).max_by_key(|&i| (self.lanes[i].current, -(i as i64)))
+        else {
+            return Vec::new();
+        };
+        self.lanes[selected].current -= total;
+
+        let mut rest: Vec<&WeightedLane> = self
+            .lanes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != selected)
+            .map(|(_, lane)| lane)
+            .collect();
+        rest.sort_by_key(|lane| -lane.weight);
+
+        std::iter::once(self.lanes[selected].queue_name.clone())
+            .chain(rest.into_iter().map(|lane| lane.queue_name.clone()))
+            .collect()
+    }
+}
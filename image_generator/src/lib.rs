@@ -0,0 +1,7 @@
+pub mod embeddings;
+pub mod lanes;
+pub mod messages;
+pub mod open_ai;
+pub mod parameters;
+pub mod processing;
+pub mod worker;
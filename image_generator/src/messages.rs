@@ -0,0 +1,147 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use database::messages::{
+    DeadLetter, GenerateImageEnvelope, GenerationParameters, GENERATE_IMAGE_MESSAGE_TYPE,
+};
+use database::queue::MessageQueue;
+use database::GenerateImageMessage;
+use serde::Deserialize;
+use tracing::{event, Level};
+
+use crate::open_ai::OPEN_AI_BACKEND;
+use crate::parameters::capabilities;
+
+/// Why a message couldn't be turned into a generation. None of these go away
+/// on redelivery, so the message is dead-lettered.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownType(String),
+    UnsupportedVersion(u64),
+    Malformed(String),
+    InvalidParameters(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownType(message_type) => {
+                write!(f, "Unknown message type {message_type}")
+            }
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported message version {version}")
+            }
+            DecodeError::Malformed(message) => write!(f, "Malformed message: {message}"),
+            DecodeError::InvalidParameters(message) => {
+                write!(f, "Invalid generation parameters: {message}")
+            }
+        }
+    }
+}
+
+/// The bare message published before the envelope existed.
+#[derive(Deserialize)]
+struct GenerateImageMessageV1 {
+    inspiration_image_id: i32,
+}
+
+/// Decodes every generate message version still in circulation into the
+/// current envelope. Version 1 messages have no envelope, so they get a new
+/// correlation id and the queue's `enqueued_at`.
+pub fn decode_generate_image(
+    message: &serde_json::Value,
+    enqueued_at: DateTime<Utc>,
+) -> Result<GenerateImageEnvelope, DecodeError> {
+    let object = message
+        .as_object()
+        .ok_or_else(|| DecodeError::Malformed("expected an object".to_string()))?;
+
+    match object.get("type") {
+        None => {
+            let v1: GenerateImageMessageV1 = serde_json::from_value(message.clone())
+                .map_err(|e| DecodeError::Malformed(e.to_string()))?;
+            return Ok(GenerateImageEnvelope {
+                version: 1,
+                enqueued_at,
+                ..GenerateImageMessage::new(v1.inspiration_image_id).into_envelope()
+            });
+        }
+        Some(serde_json::Value::String(message_type))
+            if message_type == GENERATE_IMAGE_MESSAGE_TYPE => {}
+        Some(message_type) => {
+            let message_type = match message_type.as_str() {
+                Some(message_type) => message_type.to_string(),
+                None => message_type.to_string(),
+            };
+            return Err(DecodeError::UnknownType(message_type));
+        }
+    }
+
+    let version = object
+        .get("version")
+        .and_then(|version| version.as_u64())
+        .ok_or_else(|| DecodeError::Malformed("missing version".to_string()))?;
+    match version {
+        2 => {
+            let envelope: GenerateImageEnvelope = serde_json::from_value(message.clone())
+                .map_err(|e| DecodeError::Malformed(e.to_string()))?;
+            validate_parameters(&envelope.payload.parameters)?;
+            Ok(envelope)
+        }
+        version => Err(DecodeError::UnsupportedVersion(version)),
+    }
+}
+
+/// Checks the parameters against what their backend supports, so senders can
+/// reject a message the generator would dead-letter.
+pub fn validate_parameters(parameters: &GenerationParameters) -> Result<(), DecodeError> {
+    let backend = parameters.backend.as_deref().unwrap_or(OPEN_AI_BACKEND);
+    let Some(capabilities) = capabilities(backend) else {
+        return Err(DecodeError::InvalidParameters(format!(
+            "unsupported backend {backend}"
+        )));
+    };
+    capabilities
+        .validate(
+            parameters.size.as_deref(),
+            parameters.quality.as_deref(),
+            parameters.style.as_deref(),
+            parameters.source.as_deref(),
+        )
+        .map_err(DecodeError::InvalidParameters)?;
+    if let Some(prompt) = &parameters.prompt_override {
+        if prompt.trim().is_empty() {
+            return Err(DecodeError::InvalidParameters(
+                "prompt override is empty".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Moves a message that couldn't be decoded to `dead_letter_queue` and
+/// archives the original so it isn't redelivered.
+pub async fn dead_letter(
+    queue: &dyn MessageQueue,
+    source_queue: &str,
+    dead_letter_queue: &str,
+    msg_id: i64,
+    message: serde_json::Value,
+    error: &DecodeError,
+) -> anyhow::Result<()> {
+    event!(
+        Level::WARN,
+        "Dead-lettering message {msg_id} from {source_queue}: {error}"
+    );
+    let dead_letter = DeadLetter {
+        source_queue: source_queue.to_string(),
+        error: error.to_string(),
+        message,
+        failed_at: Utc::now(),
+    };
+    queue
+        .send(dead_letter_queue, &serde_json::to_value(&dead_letter)?)
+        .await?;
+    queue.archive(source_queue, msg_id).await?;
+    Ok(())
+}
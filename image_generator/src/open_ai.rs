@@ -0,0 +1,420 @@
+use std::fmt;
+
+use database::costs::{PriceKey, PriceTable};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde_json::json;
+use tracing::{event, instrument, Level};
+
+use crate::parameters::ImageOptions;
+
+pub const OPEN_AI_BASE_URL: &str = "https://api.openai.com";
+/// The backend name generations are priced and requested under.
+pub const OPEN_AI_BACKEND: &str = "openai";
+pub const IMAGE_MODEL: &str = "dall-e-3";
+pub const DEFAULT_IMAGE_QUALITY: &str = "standard";
+pub const DEFAULT_IMAGE_STYLE: &str = "vivid";
+pub const EMBEDDING_MODEL: &str = "text-embedding-3-small";
+pub const DEFAULT_IMAGE_SIZE: &str = "1024x1024";
+/// The sizes DALL·E 3 accepts.
+pub const SUPPORTED_IMAGE_SIZES: [&str; 3] = ["1024x1024", "1792x1024", "1024x1792"];
+pub const SUPPORTED_IMAGE_QUALITIES: [&str; 2] = ["standard", "hd"];
+pub const SUPPORTED_IMAGE_STYLES: [&str; 2] = ["vivid", "natural"];
+/// Describes inspiration images that are generated from by vision.
+pub const VISION_MODEL: &str = "gpt-4o";
+/// How closely the vision model looks at an image. Captions are priced under
+/// this in place of a quality, with `caption` as the size.
+pub const CAPTION_DETAIL: &str = "low";
+const CAPTION_MAX_TOKENS: u32 = 300;
+const CAPTION_INSTRUCTIONS: &str = "Describe this photograph as a detailed prompt for an \
+    image generation model, in one paragraph covering the subject, setting, composition, \
+    lighting, colour and mood. Leave out any text, logos and the names of people.";
+/// The only model with a variations endpoint.
+pub const VARIATION_MODEL: &str = "dall-e-2";
+/// The sizes DALL·E 2 makes variations in.
+pub const VARIATION_SIZES: [&str; 3] = ["256x256", "512x512", "1024x1024"];
+
+#[derive(serde::Deserialize, Debug)]
+pub struct GeneratedImageResponse {
+    pub created: u64,
+    pub data: Vec<GeneratedImage>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct GeneratedImage {
+    pub b64_json: String,
+    /// Left empty for variations, which have no prompt to revise.
+    #[serde(default)]
+    pub revised_prompt: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ChatCompletionResponse {
+    pub choices: Vec<ChatCompletionChoice>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ChatCompletionChoice {
+    pub message: ChatMessage,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ChatMessage {
+    pub content: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct EmbeddingResponse {
+    pub data: Vec<Embedding>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Embedding {
+    pub embedding: Vec<f32>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct OpenAiErrorResponse {
+    pub error: OpenAiError,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct OpenAiError {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub error_type: Option<String>,
+    pub code: Option<String>,
+}
+
+/// Classifies a failed generation so the caller knows whether the queue
+/// message should be left for redelivery or archived.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GenerationError {
+    /// The prompt was rejected by the safety system. Retrying the same prompt
+    /// will fail again, but a rewritten prompt may succeed.
+    ContentPolicy(String),
+    /// Transient failures such as rate limits, timeouts and server errors.
+    Retryable(String),
+    /// Failures that will not resolve by retrying the same request.
+    Permanent(String),
+}
+
+impl GenerationError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, GenerationError::Retryable(_))
+    }
+
+    fn from_response(status: StatusCode, body: &str) -> Self {
+        let error = serde_json::from_str::<OpenAiErrorResponse>(body)
+            .map(|res| res.error)
+            .ok();
+        let message = match &error {
+            Some(error) => error.message.clone(),
+            None => format!("{status}: {body}"),
+        };
+        let code = error
+            .as_ref()
+            .and_then(|error| error.code.as_deref().or(error.error_type.as_deref()));
+
+        match code {
+            Some("content_policy_violation") => return GenerationError::ContentPolicy(message),
+            Some("billing_hard_limit_reached") | Some("insufficient_quota") => {
+                return GenerationError::Permanent(message)
+            }
+            _ => {}
+        }
+
+        // Auth failures are a deployment problem rather than a problem with the
+        // message, so leave the message on the queue until the key is fixed.
+        if status.is_server_error()
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::UNAUTHORIZED
+            || status == StatusCode::REQUEST_TIMEOUT
+        {
+            return GenerationError::Retryable(message);
+        }
+
+        GenerationError::Permanent(message)
+    }
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerationError::ContentPolicy(message) => {
+                write!(f, "Prompt rejected by content policy: {message}")
+            }
+            GenerationError::Retryable(message) => write!(f, "Retryable error: {message}"),
+            GenerationError::Permanent(message) => write!(f, "Permanent error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
+
+#[derive(Debug, Clone)]
+pub struct OpenAiClient {
+    http_client: Client,
+    base_url: String,
+    access_key: String,
+    prices: PriceTable,
+}
+
+impl OpenAiClient {
+    pub fn new(base_url: String, access_key: String) -> Self {
+        Self {
+            http_client: Client::new(),
+            base_url,
+            access_key,
+            prices: PriceTable::default(),
+        }
+    }
+
+    pub fn with_prices(mut self, prices: PriceTable) -> Self {
+        self.prices = prices;
+        self
+    }
+
+    fn priced(&self, key: PriceKey) -> (PriceKey, i64) {
+        let cost = self.prices.cost(&key);
+        (key, cost)
+    }
+
+    /// What one image generated with `options` is billed at.
+    pub fn image_cost(&self, options: &ImageOptions) -> (PriceKey, i64) {
+        self.priced(PriceKey::new(
+            OPEN_AI_BACKEND,
+            IMAGE_MODEL,
+            &options.size,
+            &options.quality,
+        ))
+    }
+
+    /// What one variation made with `options` is billed at.
+    pub fn variation_cost(&self, options: &ImageOptions) -> (PriceKey, i64) {
+        self.priced(PriceKey::new(
+            OPEN_AI_BACKEND,
+            VARIATION_MODEL,
+            &options.size,
+            &options.quality,
+        ))
+    }
+
+    /// What captioning one image is billed at, an estimate since vision is
+    /// billed by the token.
+    pub fn caption_cost(&self) -> (PriceKey, i64) {
+        self.priced(PriceKey::new(
+            OPEN_AI_BACKEND,
+            VISION_MODEL,
+            "caption",
+            CAPTION_DETAIL,
+        ))
+    }
+
+    /// Sends the request with the access key and returns the body of a
+    /// successful response, classifying anything else.
+    async fn send(&self, request: RequestBuilder, action: &str) -> Result<String, GenerationError> {
+        let res = request
+            .header("Authorization", format!("Bearer {}", self.access_key))
+            .send()
+            .await
+            .map_err(|e| GenerationError::Retryable(e.to_string()))?;
+
+        let status = res.status();
+        let text = res
+            .text()
+            .await
+            .map_err(|e| GenerationError::Retryable(e.to_string()))?;
+
+        if !status.is_success() {
+            let error = GenerationError::from_response(status, &text);
+            event!(Level::WARN, "{action} failed: {error}");
+            return Err(error);
+        }
+        Ok(text)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn generate_image(
+        &self,
+        prompt: &str,
+        options: &ImageOptions,
+    ) -> Result<GeneratedImageResponse, GenerationError> {
+        let url = format!("{}/v1/images/generations", self.base_url);
+        let body = json!({
+          "model": IMAGE_MODEL,
+          "prompt": prompt,
+          "n": 1,
+          "size": options.size,
+          "quality": options.quality,
+          "style": options.style,
+          "response_format" : "b64_json"
+        });
+
+        event!(Level::INFO, "Generating Image");
+        let request = self
+            .http_client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .json(&body);
+        let text = self.send(request, "Image generation").await?;
+        let json = parse_generated_images(&text)?;
+
+        event!(Level::INFO, "Image Generated");
+        Ok(json)
+    }
+
+    /// Makes a variation of `png`, which has to be a square PNG under 4MB
+    /// such as [`crate::processing::variation_png`] returns.
+    #[instrument(skip(self, png))]
+    pub async fn create_variation(
+        &self,
+        png: Vec<u8>,
+        options: &ImageOptions,
+    ) -> Result<GeneratedImageResponse, GenerationError> {
+        let url = format!("{}/v1/images/variations", self.base_url);
+        let image = Part::bytes(png)
+            .file_name("image.png")
+            .mime_str("image/png")
+            .map_err(|e| GenerationError::Permanent(e.to_string()))?;
+        let form = Form::new()
+            .part("image", image)
+            .text("model", VARIATION_MODEL)
+            .text("n", "1")
+            .text("size", options.size.clone())
+            .text("response_format", "b64_json");
+
+        event!(Level::INFO, "Generating image variation");
+        let request = self.http_client.post(url).multipart(form);
+        let text = self.send(request, "Image variation").await?;
+        let json = parse_generated_images(&text)?;
+
+        event!(Level::INFO, "Image variation generated");
+        Ok(json)
+    }
+
+    /// Describes an image, passed inline as `mime_type` data, in enough detail
+    /// to generate from.
+    #[instrument(skip(self, image_data))]
+    pub async fn caption_image(
+        &self,
+        image_data: &[u8],
+        mime_type: &str,
+    ) -> Result<String, GenerationError> {
+        let url = format!("{}/v1/chat/completions", self.base_url);
+        let image_url = format!("data:{mime_type};base64,{}", base64::encode(image_data));
+        let body = json!({
+          "model": VISION_MODEL,
+          "max_tokens": CAPTION_MAX_TOKENS,
+          "messages": [{
+            "role": "user",
+            "content": [
+              { "type": "text", "text": CAPTION_INSTRUCTIONS },
+              { "type": "image_url", "image_url": { "url": image_url, "detail": CAPTION_DETAIL } }
+            ]
+          }]
+        });
+
+        event!(Level::INFO, "Captioning image");
+        let request = self
+            .http_client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .json(&body);
+        let text = self.send(request, "Captioning").await?;
+
+        serde_json::from_str::<ChatCompletionResponse>(&text)
+            .map_err(|e| GenerationError::Permanent(format!("Unexpected response body: {e}")))?
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .map(|caption| caption.trim().to_string())
+            .filter(|caption| !caption.is_empty())
+            .ok_or_else(|| GenerationError::Permanent("No caption returned".to_string()))
+    }
+
+    #[instrument(skip(self, input))]
+    pub async fn create_embedding(&self, input: &str) -> Result<Vec<f32>, GenerationError> {
+        let url = format!("{}/v1/embeddings", self.base_url);
+        let body = json!({
+          "model": EMBEDDING_MODEL,
+          "input": input,
+        });
+
+        let request = self
+            .http_client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .json(&body);
+        let text = self.send(request, "Embedding").await?;
+
+        serde_json::from_str::<EmbeddingResponse>(&text)
+            .map_err(|e| GenerationError::Permanent(format!("Unexpected response body: {e}")))?
+            .data
+            .into_iter()
+            .next()
+            .map(|data| data.embedding)
+            .ok_or_else(|| GenerationError::Permanent("No embedding returned".to_string()))
+    }
+
+    /// Generates an image for the prompt, rewriting it and trying once more if
+    /// the first attempt is rejected by the content policy. Returns the response
+    /// along with the prompt that produced it.
+    pub async fn generate_image_with_policy_retry(
+        &self,
+        prompt: String,
+        options: &ImageOptions,
+    ) -> Result<(GeneratedImageResponse, String), GenerationError> {
+        match self.generate_image(&prompt, options).await {
+            Ok(response) => Ok((response, prompt)),
+            Err(GenerationError::ContentPolicy(message)) => {
+                event!(
+                    Level::WARN,
+                    "Prompt rejected by content policy, retrying with sanitized prompt: {message}"
+                );
+                let sanitized_prompt = sanitize_prompt(&prompt);
+                let response = self.generate_image(&sanitized_prompt, options).await?;
+                Ok((response, sanitized_prompt))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn parse_generated_images(text: &str) -> Result<GeneratedImageResponse, GenerationError> {
+    let json = serde_json::from_str::<GeneratedImageResponse>(text)
+        .map_err(|e| GenerationError::Permanent(format!("Unexpected response body: {e}")))?;
+    if json.data.is_empty() {
+        return Err(GenerationError::Permanent("No image generated".to_string()));
+    }
+    Ok(json)
+}
+
+/// Words that commonly trip the safety system on otherwise harmless photo
+/// descriptions.
+const FLAGGED_WORDS: [&str; 12] = [
+    "blood", "bloody", "gun", "guns", "knife", "weapon", "naked", "nude", "kill", "dead", "shoot",
+    "shot",
+];
+
+/// Rewrites a prompt that was rejected for content policy. Removes links,
+/// handles and flagged words, then frames what is left as an artistic
+/// interpretation.
+pub fn sanitize_prompt(prompt: &str) -> String {
+    let words: Vec<&str> = prompt
+        .split_whitespace()
+        .filter(|word| !word.starts_with("http") && !word.starts_with('@'))
+        .filter(|word| {
+            let normalized = word
+                .trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase();
+            !FLAGGED_WORDS.contains(&normalized.as_str())
+        })
+        .collect();
+
+    format!(
+        "A tasteful, family friendly artistic photograph inspired by: {}",
+        words.join(" ")
+    )
+}
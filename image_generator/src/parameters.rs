@@ -0,0 +1,267 @@
+use anyhow::anyhow;
+use database::messages::GenerationParameters;
+use database::prompt_drift::VARIATION_SOURCE;
+
+use crate::open_ai::{
+    DEFAULT_IMAGE_QUALITY, DEFAULT_IMAGE_SIZE, DEFAULT_IMAGE_STYLE, OPEN_AI_BACKEND,
+    SUPPORTED_IMAGE_QUALITIES, SUPPORTED_IMAGE_SIZES, SUPPORTED_IMAGE_STYLES, VARIATION_SIZES,
+};
+
+/// A size that picks whichever supported size is closest to the inspiration
+/// image's aspect ratio.
+pub const AUTO_SIZE: &str = "auto";
+/// How much wider than tall, or taller than wide, an image has to be before
+/// it gets a landscape or portrait size.
+const ASPECT_RATIO_THRESHOLD: f64 = 1.2;
+
+/// What an image is generated from, saved on the generated image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerationSource {
+    /// The inspiration image's description.
+    Description,
+    /// The message's prompt override, which is always used when set.
+    PromptOverride,
+    /// A description of the inspiration image written by a vision model.
+    Vision,
+    /// A variation of the inspiration image itself, without a prompt.
+    Variation,
+}
+
+/// The sources a message or `GENERATION_SOURCE` can ask for.
+pub const SUPPORTED_SOURCES: [&str; 3] = ["description", "vision", "variation"];
+
+impl GenerationSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GenerationSource::Description => "description",
+            GenerationSource::PromptOverride => "prompt_override",
+            GenerationSource::Vision => "vision",
+            GenerationSource::Variation => VARIATION_SOURCE,
+        }
+    }
+
+    /// Parses one of the [`SUPPORTED_SOURCES`].
+    pub fn parse(source: &str) -> Option<Self> {
+        match source {
+            "description" => Some(GenerationSource::Description),
+            "vision" => Some(GenerationSource::Vision),
+            "variation" => Some(GenerationSource::Variation),
+            _ => None,
+        }
+    }
+}
+
+/// The sizes, qualities, styles and sources a backend accepts.
+#[derive(Debug)]
+pub struct BackendCapabilities {
+    pub name: &'static str,
+    pub sizes: &'static [&'static str],
+    pub qualities: &'static [&'static str],
+    pub styles: &'static [&'static str],
+    pub sources: &'static [&'static str],
+}
+
+pub const OPEN_AI_CAPABILITIES: BackendCapabilities = BackendCapabilities {
+    name: OPEN_AI_BACKEND,
+    sizes: &SUPPORTED_IMAGE_SIZES,
+    qualities: &SUPPORTED_IMAGE_QUALITIES,
+    styles: &SUPPORTED_IMAGE_STYLES,
+    sources: &SUPPORTED_SOURCES,
+};
+
+pub const BACKENDS: [&BackendCapabilities; 1] = [&OPEN_AI_CAPABILITIES];
+
+pub fn capabilities(backend: &str) -> Option<&'static BackendCapabilities> {
+    BACKENDS
+        .into_iter()
+        .find(|capabilities| capabilities.name == backend)
+}
+
+impl BackendCapabilities {
+    /// Checks each parameter that is set, `auto` being a valid size for every
+    /// backend.
+    pub fn validate(
+        &self,
+        size: Option<&str>,
+        quality: Option<&str>,
+        style: Option<&str>,
+        source: Option<&str>,
+    ) -> Result<(), String> {
+        let check = |kind: &str, value: Option<&str>, supported: &[&str]| match value {
+            Some(value) if !supported.contains(&value) => {
+                Err(format!("unsupported {kind} {value} for {}", self.name))
+            }
+            _ => Ok(()),
+        };
+        check("size", size.filter(|size| *size != AUTO_SIZE), self.sizes)?;
+        check("quality", quality, self.qualities)?;
+        check("style", style, self.styles)?;
+        check("source", source, self.sources)
+    }
+
+    /// The supported size closest in shape to `width` by `height`.
+    pub fn size_for_aspect_ratio(&self, width: i32, height: i32) -> &'static str {
+        let ratio = width as f64 / height as f64;
+        let wanted = if ratio >= ASPECT_RATIO_THRESHOLD {
+            "landscape"
+        } else if ratio <= 1.0 / ASPECT_RATIO_THRESHOLD {
+            "portrait"
+        } else {
+            "square"
+        };
+        self.sizes
+            .iter()
+            .copied()
+            .find(|size| shape(size) == Some(wanted))
+            .unwrap_or(DEFAULT_IMAGE_SIZE)
+    }
+}
+
+/// The width and height of a `WIDTHxHEIGHT` size.
+pub fn size_dimensions(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+fn shape(size: &str) -> Option<&'static str> {
+    let (width, height) = size_dimensions(size)?;
+    Some(match width.cmp(&height) {
+        std::cmp::Ordering::Greater => "landscape",
+        std::cmp::Ordering::Less => "portrait",
+        std::cmp::Ordering::Equal => "square",
+    })
+}
+
+/// What a single image is generated with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageOptions {
+    pub size: String,
+    pub quality: String,
+    pub style: String,
+}
+
+impl ImageOptions {
+    /// Variations only come in square sizes at one quality, so anything else
+    /// falls back to the largest square.
+    pub fn for_variation(self) -> Self {
+        let size = if VARIATION_SIZES.contains(&self.size.as_str()) {
+            self.size
+        } else {
+            VARIATION_SIZES[VARIATION_SIZES.len() - 1].to_string()
+        };
+        Self {
+            size,
+            quality: DEFAULT_IMAGE_QUALITY.to_string(),
+            ..self
+        }
+    }
+}
+
+/// The generator-wide parameters, used for anything a message leaves out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenerationDefaults {
+    pub size: String,
+    pub quality: String,
+    pub style: String,
+    pub source: GenerationSource,
+}
+
+impl Default for GenerationDefaults {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_IMAGE_SIZE.to_string(),
+            quality: DEFAULT_IMAGE_QUALITY.to_string(),
+            style: DEFAULT_IMAGE_STYLE.to_string(),
+            source: GenerationSource::Description,
+        }
+    }
+}
+
+impl GenerationDefaults {
+    /// Reads `IMAGE_SIZE`, `IMAGE_QUALITY`, `IMAGE_STYLE` and
+    /// `GENERATION_SOURCE`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
+        let lookup = |name: &str| lookup(name).filter(|value| !value.is_empty());
+        let defaults = Self::default();
+        let config = Self {
+            size: lookup("IMAGE_SIZE").unwrap_or(defaults.size),
+            quality: lookup("IMAGE_QUALITY").unwrap_or(defaults.quality),
+            style: lookup("IMAGE_STYLE").unwrap_or(defaults.style),
+            source: defaults.source,
+        };
+        let source = lookup("GENERATION_SOURCE");
+        OPEN_AI_CAPABILITIES
+            .validate(
+                Some(&config.size),
+                Some(&config.quality),
+                Some(&config.style),
+                source.as_deref(),
+            )
+            .map_err(|e| anyhow!("Invalid generation defaults: {e}"))?;
+        Ok(Self {
+            source: source
+                .as_deref()
+                .and_then(GenerationSource::parse)
+                .unwrap_or(config.source),
+            ..config
+        })
+    }
+
+    /// What to generate from. A prompt override always wins, and an image
+    /// without a description is captioned instead.
+    pub fn source(
+        &self,
+        parameters: &GenerationParameters,
+        has_description: bool,
+    ) -> GenerationSource {
+        if parameters.prompt_override.is_some() {
+            return GenerationSource::PromptOverride;
+        }
+        let source = parameters
+            .source
+            .as_deref()
+            .and_then(GenerationSource::parse)
+            .unwrap_or(self.source);
+        match source {
+            GenerationSource::Description if !has_description => GenerationSource::Vision,
+            source => source,
+        }
+    }
+
+    /// The message's parameters, falling back to the defaults. An `auto`
+    /// size matches `dimensions` when the inspiration image has them.
+    pub fn resolve(
+        &self,
+        parameters: &GenerationParameters,
+        dimensions: Option<(i32, i32)>,
+    ) -> ImageOptions {
+        let backend = parameters
+            .backend
+            .as_deref()
+            .and_then(capabilities)
+            .unwrap_or(&OPEN_AI_CAPABILITIES);
+        let size = parameters.size.as_deref().unwrap_or(&self.size);
+        let size = match (size, dimensions) {
+            (AUTO_SIZE, Some((width, height))) if width > 0 && height > 0 => {
+                backend.size_for_aspect_ratio(width, height)
+            }
+            (AUTO_SIZE, _) => DEFAULT_IMAGE_SIZE,
+            (size, _) => size,
+        };
+        ImageOptions {
+            size: size.to_string(),
+            quality: parameters
+                .quality
+                .clone()
+                .unwrap_or_else(|| self.quality.clone()),
+            style: parameters
+                .style
+                .clone()
+                .unwrap_or_else(|| self.style.clone()),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::Cursor;
+use std::sync::Arc;
+
+use database::entity::image_derivative::Model as ImageDerivativeModel;
+use database::entity::sea_orm_active_enums::ImageKind;
+use database::repository::{ImageDerivatives, NewImageDerivative};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use sea_orm::{ActiveEnum, DatabaseConnection};
+use storage::Storage;
+use tracing::{event, instrument, Level};
+
+/// Widths generated for every image, used to build the `srcset` in the gallery.
+pub const DERIVATIVE_WIDTHS: [u32; 3] = [256, 512, 1024];
+
+#[derive(Debug)]
+pub struct Derivative {
+    pub format: &'static str,
+    pub mime_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+fn output_formats(
+    source_format: Option<ImageFormat>,
+) -> Vec<(ImageOutputFormat, &'static str, &'static str)> {
+    // Photos stay JPEG for browsers without WebP support, everything else
+    // falls back to PNG.
+    let fallback = match source_format {
+        Some(ImageFormat::Jpeg) => (ImageOutputFormat::Jpeg(85), "jpeg", "image/jpeg"),
+        _ => (ImageOutputFormat::Png, "png", "image/png"),
+    };
+
+    #[allow(unused_mut)]
+    let mut formats = vec![fallback, (ImageOutputFormat::WebP, "webp", "image/webp")];
+    #[cfg(feature = "avif")]
+    formats.push((ImageOutputFormat::Avif, "avif", "image/avif"));
+    formats
+}
+
+/// Resizes an encoded image to each of the `DERIVATIVE_WIDTHS` that fit inside
+/// it and encodes every size in each output format.
+pub fn create_derivatives(data: &[u8]) -> anyhow::Result<Vec<Derivative>> {
+    let source_format = image::guess_format(data).ok();
+    let source = image::load_from_memory(data)?;
+
+    let mut widths: Vec<u32> = DERIVATIVE_WIDTHS
+        .into_iter()
+        .filter(|width| *width <= source.width())
+        .collect();
+    if widths.is_empty() {
+        widths.push(source.width());
+    }
+
+    let mut derivatives = Vec::new();
+    for width in widths {
+        let resized = source.resize(width, u32::MAX, FilterType::Lanczos3);
+        for (output_format, format, mime_type) in output_formats(source_format) {
+            let mut encoded = Cursor::new(Vec::new());
+            resized.write_to(&mut encoded, output_format)?;
+            derivatives.push(Derivative {
+                format,
+                mime_type,
+                width: resized.width(),
+                height: resized.height(),
+                data: encoded.into_inner(),
+            });
+        }
+    }
+
+    Ok(derivatives)
+}
+
+/// Crops the image to a centred square `side` pixels across and encodes it
+/// as the PNG the variations endpoint wants. Dropping the alpha channel keeps
+/// the largest size under the endpoint's 4MB limit.
+pub fn variation_png(data: &[u8], side: u32) -> anyhow::Result<Vec<u8>> {
+    let source = image::load_from_memory(data)?;
+    let square = source.width().min(source.height());
+    let cropped = source.crop_imm(
+        (source.width() - square) / 2,
+        (source.height() - square) / 2,
+        square,
+        square,
+    );
+    let resized = cropped.resize_exact(side, side, FilterType::Lanczos3);
+
+    let mut encoded = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(resized.to_rgb8()).write_to(&mut encoded, ImageOutputFormat::Png)?;
+    Ok(encoded.into_inner())
+}
+
+#[instrument(skip(db, storage, data))]
+pub async fn process_image(
+    db: &DatabaseConnection,
+    storage: Arc<dyn Storage>,
+    image_kind: ImageKind,
+    image_id: i32,
+    data: Vec<u8>,
+) -> anyhow::Result<Vec<ImageDerivativeModel>> {
+    let derivatives = tokio::task::spawn_blocking(move || create_derivatives(&data)).await??;
+
+    let mut saved = Vec::new();
+    for derivative in derivatives {
+        let key = format!(
+            "derivatives/{}/{}/{}.{}",
+            image_kind.to_value(),
+            image_id,
+            derivative.width,
+            derivative.format
+        );
+        storage
+            .put(&key, derivative.data, derivative.mime_type)
+            .await?;
+
+        let derivative = NewImageDerivative {
+            image_kind,
+            image_id,
+            format: derivative.format.to_string(),
+            mime_type: derivative.mime_type.to_string(),
+            width: derivative.width as i32,
+            height: derivative.height as i32,
+            url: storage.url(&key),
+            storage_key: key,
+        };
+        saved.push(ImageDerivatives::new(db).upsert(derivative).await?);
+    }
+
+    event!(Level::INFO, "Saved {} image derivatives", saved.len());
+    Ok(saved)
+}
+
+#[instrument]
+pub async fn download_image(url: &str) -> anyhow::Result<Vec<u8>> {
+    let res = reqwest::get(url).await?.error_for_status()?;
+    Ok(res.bytes().await?.to_vec())
+}
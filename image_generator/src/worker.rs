@@ -0,0 +1,512 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use base64::decode;
+use chrono::Utc;
+use database::costs::{
+    current_spend, record_cost, Budget, BudgetPeriod, NewGenerationCost, PriceKey,
+};
+use database::entity::generated_image::Model as GeneratedImageModel;
+use database::entity::inspiration_image::Model;
+use database::entity::sea_orm_active_enums::{GenerationStatus, ImageKind};
+use database::messages::{GenerateImageEnvelope, GENERATE_IMAGE_DLQ};
+use database::outbox::{relay_outbox, DEFAULT_RELAY_BATCH_SIZE};
+use database::queue::MessageQueue;
+use database::repository::{GeneratedImages, InspirationImages, NewGeneratedImage};
+use database::tags::tag_from_prompt;
+use database::GenerateImageMessage;
+use sea_orm::DatabaseConnection;
+use storage::Storage;
+use tracing::{event, instrument, Level};
+
+use crate::embeddings::embed_generated_image;
+use crate::lanes::WeightedLanes;
+use crate::messages::{dead_letter, decode_generate_image};
+use crate::open_ai::{GeneratedImageResponse, GenerationError, OpenAiClient};
+use crate::parameters::{size_dimensions, GenerationDefaults, GenerationSource, ImageOptions};
+use crate::processing::{download_image, process_image, variation_png};
+
+/// How long a message stays hidden from other generators while it is being
+/// handled before it is redelivered.
+pub const VISIBILITY_TIMEOUT_SECS: i32 = 60;
+
+/// How long to wait before reading again when every lane is empty. Short, so
+/// a priority message doesn't wait long behind an idle generator.
+pub const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait before checking a spent budget again.
+pub const BUDGET_PAUSE: Duration = Duration::from_secs(5 * 60);
+
+/// What [`process_next_message`] did with the message it read, if any.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Processed {
+    /// Every lane was empty.
+    Idle,
+    /// The message was handled and archived.
+    Completed,
+    /// The message couldn't be decoded and was moved to the dead letter queue.
+    DeadLettered,
+    /// Handling failed, so the message is left to be redelivered.
+    Failed,
+}
+
+async fn record_generation_outcome(
+    db: &DatabaseConnection,
+    inspiration_image_id: i32,
+    status: GenerationStatus,
+    error: Option<String>,
+) -> anyhow::Result<()> {
+    InspirationImages::new(db)
+        .set_generation_status(inspiration_image_id, status, error)
+        .await?;
+
+    Ok(())
+}
+
+async fn parse_image_response(
+    response: GeneratedImageResponse,
+) -> anyhow::Result<(Vec<u8>, String)> {
+    if let Some(data) = response.data.first() {
+        match decode(&data.b64_json) {
+            Ok(image_data) => return Ok((image_data, data.revised_prompt.clone())),
+
+            Err(e) => return Err(anyhow!(e)),
+        }
+    };
+
+    Err(anyhow!("No image data"))
+}
+
+/// Generated images are stored under a key derived from the message, so a
+/// redelivered message overwrites its own upload instead of leaving an orphan.
+fn generated_image_storage_key(message: &GenerateImageMessage) -> String {
+    match &message.request_id {
+        Some(request_id) => format!(
+            "generated/{}-{request_id}.png",
+            message.inspiration_image_id
+        ),
+        None => format!("generated/{}.png", message.inspiration_image_id),
+    }
+}
+
+/// Records work the backend bills for. It is billed whether or not the rest
+/// succeeds, so a failure to record it shouldn't send the message round again.
+async fn record_billed(
+    db: &DatabaseConnection,
+    message: &GenerateImageMessage,
+    (key, cost_micros): (PriceKey, i64),
+) {
+    if let Err(e) = record_cost(
+        db,
+        NewGenerationCost {
+            inspiration_image_id: message.inspiration_image_id,
+            idempotency_key: Some(message.idempotency_key()),
+            key,
+            cost_micros,
+        },
+    )
+    .await
+    {
+        event!(Level::WARN, "Error recording generation cost: {e}");
+    }
+}
+
+/// The archived copy of the inspiration image when there is one, otherwise
+/// the upstream image.
+async fn inspiration_image_data(
+    storage: &Arc<dyn Storage>,
+    inspiration_image: &Model,
+) -> anyhow::Result<Vec<u8>> {
+    match &inspiration_image.archived_key {
+        Some(key) => storage.get(key).await,
+        None => download_image(&inspiration_image.source_url).await,
+    }
+}
+
+/// Generates an image from `source`, returning the response along with the
+/// prompt behind it. A vision caption is billed and saved as soon as it is
+/// written, and a saved one is reused.
+async fn generate(
+    db: &DatabaseConnection,
+    client: &OpenAiClient,
+    storage: &Arc<dyn Storage>,
+    inspiration_image: &Model,
+    message: &GenerateImageMessage,
+    source: GenerationSource,
+    options: &ImageOptions,
+) -> Result<(GeneratedImageResponse, String), GenerationError> {
+    let description = inspiration_image.description.clone().unwrap_or_default();
+    let prompt = match source {
+        GenerationSource::PromptOverride => message
+            .parameters
+            .prompt_override
+            .clone()
+            .unwrap_or_default(),
+        GenerationSource::Description => description,
+        GenerationSource::Vision => match &inspiration_image.caption {
+            Some(caption) if !caption.trim().is_empty() => caption.clone(),
+            _ => {
+                let data = inspiration_image_data(storage, inspiration_image)
+                    .await
+                    .map_err(|e| GenerationError::Retryable(e.to_string()))?;
+                let mime_type = inspiration_image
+                    .mime_type
+                    .as_deref()
+                    .unwrap_or("image/jpeg");
+                let caption = client.caption_image(&data, mime_type).await?;
+                record_billed(db, message, client.caption_cost()).await;
+                // Saved before generating so that a redelivery after a failed
+                // generation doesn't pay for the caption again.
+                if let Err(e) = InspirationImages::new(db)
+                    .set_caption(inspiration_image.id, caption.clone())
+                    .await
+                {
+                    event!(Level::WARN, "Error saving caption: {e}");
+                }
+                caption
+            }
+        },
+        GenerationSource::Variation => {
+            let data = inspiration_image_data(storage, inspiration_image)
+                .await
+                .map_err(|e| GenerationError::Retryable(e.to_string()))?;
+            let side = size_dimensions(&options.size).map_or(1024, |(width, _)| width);
+            let png = tokio::task::spawn_blocking(move || variation_png(&data, side))
+                .await
+                .map_err(|e| GenerationError::Permanent(e.to_string()))?
+                .map_err(|e| {
+                    GenerationError::Permanent(format!("Error preparing image for variation: {e}"))
+                })?;
+            let response = client.create_variation(png, options).await?;
+            // Variations aren't prompted, the description is kept for tags
+            // and search.
+            return Ok((response, description));
+        }
+    };
+    client
+        .generate_image_with_policy_retry(prompt, options)
+        .await
+}
+
+#[instrument(skip_all, fields(correlation_id = %envelope.correlation_id, version = envelope.version))]
+pub async fn handle_message(
+    envelope: &GenerateImageEnvelope,
+    db: &DatabaseConnection,
+    client: &OpenAiClient,
+    storage: Arc<dyn Storage>,
+    defaults: &GenerationDefaults,
+) -> anyhow::Result<()> {
+    let message = &envelope.payload;
+    let inspiration_image_id = message.inspiration_image_id;
+    let idempotency_key = message.idempotency_key();
+    let inspiration_image = InspirationImages::new(db)
+        .find(inspiration_image_id)
+        .await?;
+
+    if let Some(inspiration_image_model) = inspiration_image {
+        if let Some(generated_image) = GeneratedImages::new(db)
+            .find_by_idempotency_key(&idempotency_key)
+            .await?
+        {
+            // A previous delivery saved the image but may have stopped before
+            // recording the outcome, so finish its bookkeeping instead of
+            // generating again.
+            if inspiration_image_model.generation_status != Some(GenerationStatus::Completed) {
+                record_generation_outcome(
+                    db,
+                    inspiration_image_id,
+                    GenerationStatus::Completed,
+                    None,
+                )
+                .await?;
+                let image_data = match &generated_image.storage_key {
+                    Some(key) => storage.get(key).await?,
+                    None => download_image(&generated_image.source_url).await?,
+                };
+                post_process(
+                    db,
+                    client,
+                    storage,
+                    &generated_image,
+                    &inspiration_image_model,
+                    image_data,
+                )
+                .await;
+            }
+            event!(
+                Level::INFO,
+                "Already generated {idempotency_key}, skipping redelivery"
+            );
+            return Ok(());
+        }
+
+        let has_description = inspiration_image_model
+            .description
+            .as_deref()
+            .is_some_and(|description| !description.trim().is_empty());
+        let source = defaults.source(&message.parameters, has_description);
+        let dimensions = inspiration_image_model
+            .width
+            .zip(inspiration_image_model.height);
+        let mut options = defaults.resolve(&message.parameters, dimensions);
+        if source == GenerationSource::Variation {
+            options = options.for_variation();
+        }
+        event!(Level::INFO, source = source.as_str(), "Generating image");
+
+        let (image_response, prompt) = match generate(
+            db,
+            client,
+            &storage,
+            &inspiration_image_model,
+            message,
+            source,
+            &options,
+        )
+        .await
+        {
+            Ok(generated) => generated,
+            Err(e) if e.is_retryable() => return Err(anyhow!(e)),
+            Err(e) => {
+                // Permanent failures are recorded and the message is archived
+                // so that it is not redelivered forever.
+                let status = match e {
+                    GenerationError::ContentPolicy(_) => GenerationStatus::Rejected,
+                    _ => GenerationStatus::Failed,
+                };
+                event!(Level::WARN, "Giving up on image generation: {e}");
+                record_generation_outcome(db, inspiration_image_id, status, Some(e.to_string()))
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let cost = match source {
+            GenerationSource::Variation => client.variation_cost(&options),
+            _ => client.image_cost(&options),
+        };
+        record_billed(db, message, cost).await;
+
+        let (image_data, revised_prompt) = parse_image_response(image_response).await?;
+        let key = generated_image_storage_key(message);
+        event!(Level::INFO, "Uploading image to storage");
+        storage.put(&key, image_data.clone(), "image/png").await?;
+
+        // A concurrent delivery of the same message may have saved the image
+        // first, in which case its row wins.
+        let generated_image = GeneratedImages::new(db)
+            .create(NewGeneratedImage {
+                inspiration_image_id,
+                source_url: storage.url(&key),
+                storage_key: Some(key),
+                idempotency_key: Some(idempotency_key),
+                prompt,
+                revised_prompt,
+                size: Some(options.size),
+                quality: Some(options.quality),
+                // Variations aren't styled.
+                style: (source != GenerationSource::Variation).then_some(options.style),
+                generation_source: Some(source.as_str().to_string()),
+            })
+            .await?;
+        record_generation_outcome(db, inspiration_image_id, GenerationStatus::Completed, None)
+            .await?;
+        event!(Level::INFO, "Saved new generated image");
+
+        post_process(
+            db,
+            client,
+            storage,
+            &generated_image,
+            &inspiration_image_model,
+            image_data,
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// The generation itself has succeeded by the time this runs, so failing to
+/// build derivatives, embeddings or tags is logged rather than causing a
+/// redelivery.
+async fn post_process(
+    db: &DatabaseConnection,
+    client: &OpenAiClient,
+    storage: Arc<dyn Storage>,
+    generated_image: &GeneratedImageModel,
+    inspiration_image: &Model,
+    image_data: Vec<u8>,
+) {
+    if let Err(e) = embed_generated_image(
+        db,
+        client,
+        generated_image,
+        inspiration_image.description.as_deref(),
+    )
+    .await
+    {
+        event!(Level::WARN, "Error embedding generated image: {e}");
+    }
+    if let Err(e) = tag_from_prompt(
+        db,
+        generated_image.id,
+        &generated_image.prompt,
+        &generated_image.revised_prompt,
+    )
+    .await
+    {
+        event!(Level::WARN, "Error tagging generated image: {e}");
+    }
+    if let Err(e) = process_image(
+        db,
+        storage.clone(),
+        ImageKind::Generated,
+        generated_image.id,
+        image_data,
+    )
+    .await
+    {
+        event!(Level::WARN, "Error processing generated image: {e}");
+    }
+    if let Err(e) = process_inspiration_image(db, storage, inspiration_image).await {
+        event!(Level::WARN, "Error processing inspiration image: {e}");
+    }
+}
+
+async fn process_inspiration_image(
+    db: &DatabaseConnection,
+    storage: Arc<dyn Storage>,
+    inspiration_image: &Model,
+) -> anyhow::Result<()> {
+    let data = inspiration_image_data(&storage, inspiration_image).await?;
+    process_image(
+        db,
+        storage,
+        ImageKind::Inspiration,
+        inspiration_image.id,
+        data,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Reads one message from the lanes in weighted order and handles it.
+pub async fn process_next_message(
+    queue: &dyn MessageQueue,
+    lanes: &mut WeightedLanes,
+    db: &DatabaseConnection,
+    client: &OpenAiClient,
+    storage: Arc<dyn Storage>,
+    defaults: &GenerationDefaults,
+) -> Processed {
+    let mut received = None;
+    for queue_name in lanes.next_order() {
+        match queue.read(&queue_name, VISIBILITY_TIMEOUT_SECS).await {
+            Ok(Some(message)) => {
+                received = Some((queue_name, message));
+                break;
+            }
+            Ok(None) => {}
+            Err(e) => event!(Level::INFO, "Error reading from {queue_name}: {e}"),
+        }
+    }
+    let Some((queue_name, message)) = received else {
+        return Processed::Idle;
+    };
+
+    let envelope = match decode_generate_image(&message.message, message.enqueued_at) {
+        Ok(envelope) => envelope,
+        Err(e) => {
+            if let Err(e) = dead_letter(
+                queue,
+                &queue_name,
+                GENERATE_IMAGE_DLQ,
+                message.msg_id,
+                message.message,
+                &e,
+            )
+            .await
+            {
+                event!(Level::WARN, "Error dead-lettering message: {e}");
+            }
+            return Processed::DeadLettered;
+        }
+    };
+    match handle_message(&envelope, db, client, storage, defaults).await {
+        Ok(_) => {
+            event!(
+                Level::INFO,
+                correlation_id = %envelope.correlation_id,
+                "Image successfully generated"
+            );
+            let _ = queue.archive(&queue_name, message.msg_id).await;
+            Processed::Completed
+        }
+        Err(e) => {
+            event!(Level::INFO, correlation_id = %envelope.correlation_id, "{e}");
+            Processed::Failed
+        }
+    }
+}
+
+/// The cap the spend so far has reached, if any.
+pub async fn budget_exceeded(
+    db: &DatabaseConnection,
+    budget: &Budget,
+) -> anyhow::Result<Option<BudgetPeriod>> {
+    if budget == &Budget::default() {
+        return Ok(None);
+    }
+    let spend = current_spend(db, Utc::now()).await?;
+    Ok(budget.exceeded(&spend))
+}
+
+/// Handles messages until the process stops. Whenever every lane is empty it
+/// relays the outbox, so regenerations requested from the admin CLI are
+/// published even when the collector isn't running, then polls again shortly.
+/// While the budget is spent nothing is read, so messages wait on the queue
+/// until the next day or month.
+pub async fn run(
+    queue: &dyn MessageQueue,
+    mut lanes: WeightedLanes,
+    db: &DatabaseConnection,
+    client: &OpenAiClient,
+    storage: Arc<dyn Storage>,
+    budget: Budget,
+    defaults: GenerationDefaults,
+) {
+    loop {
+        match budget_exceeded(db, &budget).await {
+            Ok(None) => {}
+            Ok(Some(period)) => {
+                event!(
+                    Level::WARN,
+                    "The {period} generation budget is spent, pausing"
+                );
+                tokio::time::sleep(BUDGET_PAUSE).await;
+                continue;
+            }
+            Err(e) => {
+                event!(
+                    Level::WARN,
+                    "Error checking generation budget, pausing: {e}"
+                );
+                tokio::time::sleep(BUDGET_PAUSE).await;
+                continue;
+            }
+        }
+        if process_next_message(queue, &mut lanes, db, client, storage.clone(), &defaults).await
+            == Processed::Idle
+        {
+            match relay_outbox(db, queue, DEFAULT_RELAY_BATCH_SIZE).await {
+                Ok(summary) if summary.sent > 0 => continue,
+                Ok(_) => {}
+                Err(e) => event!(Level::WARN, "Error relaying outbox messages: {e}"),
+            }
+            event!(Level::DEBUG, "Queues are empty, polling again shortly");
+            tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+        }
+    }
+}
@@ -0,0 +1,960 @@
+#[cfg(test)]
+mod tests {
+    use database::costs::{Budget, PriceKey, PriceTable};
+    use database::entity::inspiration_image::ActiveModel as InspirationImageActiveModel;
+    use database::entity::sea_orm_active_enums::{GenerationStatus, ImageKind};
+    use database::messages::{DeadLetter, GenerationParameters, GENERATE_IMAGE_DLQ};
+    use database::queue::{InMemoryQueue, MessageQueue};
+    use database::repository::{
+        GeneratedImages, ImageDerivatives, InspirationImages, NewInspirationImage,
+    };
+    use database::{create_generate_image_queues, GenerateImageMessage, Lane};
+    use image_generator::lanes::WeightedLanes;
+    use image_generator::messages::{decode_generate_image, DecodeError};
+    use image_generator::open_ai::{
+        sanitize_prompt, GenerationError, OpenAiClient, DEFAULT_IMAGE_SIZE, EMBEDDING_MODEL,
+    };
+    use image_generator::parameters::{
+        capabilities, GenerationDefaults, GenerationSource, ImageOptions, AUTO_SIZE,
+    };
+    use image_generator::processing::{create_derivatives, variation_png};
+    use image_generator::worker::{
+        budget_exceeded, handle_message, process_next_message, Processed,
+    };
+    use migration::testing::migrated_database;
+    use sea_orm::{ActiveModelTrait, DatabaseConnection, Set};
+    use serde_json::json;
+    use std::io::Cursor;
+    use std::sync::Arc;
+    use storage::{LocalStorage, Storage};
+    use wiremock::matchers::{any, body_partial_json, body_string_contains, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_generate_image_classifies_content_policy_errors() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_json(open_ai_error_stub("content_policy_violation")),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let result = client
+            .generate_image("a bloody knife", &default_options())
+            .await;
+
+        assert!(matches!(result, Err(GenerationError::ContentPolicy(_))));
+    }
+
+    #[tokio::test]
+    async fn test_generate_image_classifies_rate_limits_as_retryable() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(429).set_body_json(open_ai_error_stub("rate_limit_exceeded")),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let result = client.generate_image("a cat", &default_options()).await;
+
+        assert!(result.unwrap_err().is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_generate_image_classifies_billing_limits_as_permanent() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_json(open_ai_error_stub("billing_hard_limit_reached")),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let result = client.generate_image("a cat", &default_options()).await;
+
+        assert!(matches!(result, Err(GenerationError::Permanent(_))));
+    }
+
+    #[tokio::test]
+    async fn test_content_policy_rejection_retries_with_sanitized_prompt() {
+        let mock_server = MockServer::start().await;
+        let prompt = "a bloody knife on a table".to_string();
+        let sanitized_prompt = sanitize_prompt(&prompt);
+
+        Mock::given(body_partial_json(json!({ "prompt": sanitized_prompt })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(open_ai_image_stub()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(body_partial_json(json!({ "prompt": prompt })))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_json(open_ai_error_stub("content_policy_violation")),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let (response, used_prompt) = client
+            .generate_image_with_policy_retry(prompt, &default_options())
+            .await
+            .unwrap();
+
+        assert_eq!(used_prompt, sanitized_prompt);
+        assert_eq!(response.data.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_embedding_returns_first_vector() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/v1/embeddings"))
+            .and(body_partial_json(json!({ "model": EMBEDDING_MODEL })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "object": "list",
+              "data": [{ "object": "embedding", "index": 0, "embedding": [0.25, -0.5, 1.0] }],
+              "model": EMBEDDING_MODEL
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let embedding = client.create_embedding("a dog on a beach").await.unwrap();
+
+        assert_eq!(embedding, vec![0.25, -0.5, 1.0]);
+    }
+
+    #[test]
+    fn test_sanitize_prompt_removes_flagged_words_and_links() {
+        let sanitized = sanitize_prompt("A bloody knife by @someone https://example.com");
+
+        assert!(!sanitized.contains("bloody"));
+        assert!(!sanitized.contains("knife"));
+        assert!(!sanitized.contains("@someone"));
+        assert!(!sanitized.contains("https://example.com"));
+    }
+
+    #[test]
+    fn test_create_derivatives_resizes_to_widths_that_fit() {
+        let mut png = Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(600, 400)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+
+        let derivatives = create_derivatives(png.get_ref()).unwrap();
+
+        let mut sizes = derivatives
+            .iter()
+            .map(|derivative| (derivative.format, derivative.width, derivative.height))
+            .collect::<Vec<_>>();
+        sizes.sort();
+        assert_eq!(
+            sizes,
+            vec![
+                ("png", 256, 171),
+                ("png", 512, 341),
+                ("webp", 256, 171),
+                ("webp", 512, 341)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_generate_image_accepts_unversioned_messages() {
+        let enqueued_at = chrono::Utc::now();
+        let envelope =
+            decode_generate_image(&json!({ "inspiration_image_id": 7 }), enqueued_at).unwrap();
+
+        assert_eq!(envelope.version, 1);
+        assert_eq!(envelope.enqueued_at, enqueued_at);
+        assert_eq!(envelope.payload, GenerateImageMessage::new(7));
+        assert!(!envelope.correlation_id.is_empty());
+    }
+
+    #[test]
+    fn test_decode_generate_image_round_trips_current_version() {
+        let mut message = GenerateImageMessage::new(7);
+        message.parameters = GenerationParameters {
+            backend: Some("openai".to_string()),
+            size: Some("1792x1024".to_string()),
+            quality: Some("hd".to_string()),
+            style: Some("natural".to_string()),
+            source: Some("vision".to_string()),
+            prompt_override: Some("a lighthouse at dusk".to_string()),
+        };
+        let envelope = message.into_envelope();
+
+        let decoded = decode_generate_image(
+            &serde_json::to_value(&envelope).unwrap(),
+            chrono::Utc::now(),
+        )
+        .unwrap();
+
+        assert_eq!(decoded, envelope);
+    }
+
+    #[test]
+    fn test_decode_generate_image_rejects_unknown_messages() {
+        let envelope = serde_json::to_value(GenerateImageMessage::new(7).into_envelope()).unwrap();
+        let decode = |overrides: serde_json::Value| {
+            let mut message = envelope.clone();
+            for (key, value) in overrides.as_object().unwrap() {
+                message[key] = value.clone();
+            }
+            decode_generate_image(&message, chrono::Utc::now()).unwrap_err()
+        };
+
+        assert_eq!(
+            decode(json!({ "type": "delete_image" })),
+            DecodeError::UnknownType("delete_image".to_string())
+        );
+        assert_eq!(
+            decode(json!({ "type": 3 })),
+            DecodeError::UnknownType("3".to_string())
+        );
+        assert_eq!(
+            decode(json!({ "version": 3 })),
+            DecodeError::UnsupportedVersion(3)
+        );
+        assert!(matches!(
+            decode(
+                json!({ "payload": { "inspiration_image_id": 7, "parameters": { "size": "10x10" } } })
+            ),
+            DecodeError::InvalidParameters(_)
+        ));
+        assert!(matches!(
+            decode(
+                json!({ "payload": { "inspiration_image_id": 7, "parameters": { "quality": "ultra" } } })
+            ),
+            DecodeError::InvalidParameters(_)
+        ));
+        assert!(matches!(
+            decode(json!({ "payload": {} })),
+            DecodeError::Malformed(_)
+        ));
+    }
+
+    #[test]
+    fn test_weighted_lanes_favour_heavier_lane_without_starving_others() {
+        let mut lanes =
+            WeightedLanes::new(vec![("priority".to_string(), 3), ("batch".to_string(), 1)]);
+
+        let firsts: Vec<String> = (0..8).map(|_| lanes.next_order().remove(0)).collect();
+        assert_eq!(firsts.iter().filter(|lane| *lane == "priority").count(), 6);
+        assert_eq!(firsts.iter().filter(|lane| *lane == "batch").count(), 2);
+
+        // Every order falls back to the other lane when the first is empty.
+        let order = lanes.next_order();
+        assert_eq!(order.len(), 2);
+        assert_ne!(order[0], order[1]);
+    }
+
+    #[tokio::test]
+    async fn test_process_next_message_dead_letters_undecodable_messages() {
+        let queue = InMemoryQueue::new();
+        create_generate_image_queues(&queue).await.unwrap();
+        let batch = Lane::Batch.queue_name();
+        queue
+            .send(batch, &json!({ "type": "resize_image", "version": 1 }))
+            .await
+            .unwrap();
+
+        let mut lanes = WeightedLanes::from_env();
+        let db = DatabaseConnection::Disconnected;
+        let defaults = GenerationDefaults::default();
+        let client = OpenAiClient::new("http://127.0.0.1:1".to_string(), "key".to_string());
+        let storage = Arc::new(LocalStorage::new(
+            std::env::temp_dir().join("generator-test"),
+            "/files".to_string(),
+        ));
+
+        let processed =
+            process_next_message(&queue, &mut lanes, &db, &client, storage.clone(), &defaults)
+                .await;
+        assert_eq!(processed, Processed::DeadLettered);
+        assert!(queue.pending(batch).is_empty());
+        assert_eq!(queue.archived(batch).len(), 1);
+
+        let dead_letters = queue.pending(GENERATE_IMAGE_DLQ);
+        assert_eq!(dead_letters.len(), 1);
+        let dead_letter: DeadLetter =
+            serde_json::from_value(dead_letters[0].message.clone()).unwrap();
+        assert_eq!(dead_letter.source_queue, batch);
+        assert_eq!(dead_letter.error, "Unknown message type resize_image");
+
+        let processed =
+            process_next_message(&queue, &mut lanes, &db, &client, storage, &defaults).await;
+        assert_eq!(processed, Processed::Idle);
+    }
+
+    #[tokio::test]
+    async fn test_process_next_message_leaves_failed_messages_for_redelivery() {
+        let database = migrated_database().await;
+        let db = &database.db;
+        let inspiration_image = InspirationImages::new(db)
+            .create(NewInspirationImage {
+                source_id: "lighthouse".to_string(),
+                source_url: "https://example.com/lighthouse.png".to_string(),
+                description: Some("A lighthouse".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let queue = InMemoryQueue::new();
+        create_generate_image_queues(&queue).await.unwrap();
+        let priority = Lane::Priority.queue_name();
+        let envelope = GenerateImageMessage::new(inspiration_image.id).into_envelope();
+        queue
+            .send(priority, &serde_json::to_value(&envelope).unwrap())
+            .await
+            .unwrap();
+
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(429).set_body_json(open_ai_error_stub("rate_limit_exceeded")),
+            )
+            .mount(&mock_server)
+            .await;
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let storage = Arc::new(LocalStorage::new(
+            std::env::temp_dir().join("generator-test"),
+            "/files".to_string(),
+        ));
+
+        let processed = process_next_message(
+            &queue,
+            &mut WeightedLanes::from_env(),
+            db,
+            &client,
+            storage,
+            &GenerationDefaults::default(),
+        )
+        .await;
+        assert_eq!(processed, Processed::Failed);
+
+        let pending = queue.pending(priority);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].read_ct, 1);
+        assert!(queue.archived(priority).is_empty());
+        // The message stays hidden until its visibility timeout runs out.
+        assert!(queue.read(priority, 30).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_process_next_message_saves_the_image_and_archives_the_message() {
+        let database = migrated_database().await;
+        let db = &database.db;
+
+        let mut png = Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(64, 64)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/v1/images/generations"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "created": 1710000000,
+              "data": [{ "b64_json": base64::encode(&png), "revised_prompt": "A lighthouse at dusk" }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "object": "list",
+              "data": [{ "object": "embedding", "index": 0, "embedding": vec![0.5; 1536] }],
+              "model": EMBEDDING_MODEL
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/lighthouse.png"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(png.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let inspiration_image = InspirationImages::new(db)
+            .create(NewInspirationImage {
+                source_id: "lighthouse".to_string(),
+                source_url: format!("{}/lighthouse.png", mock_server.uri()),
+                description: Some("A lighthouse".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let queue = InMemoryQueue::new();
+        create_generate_image_queues(&queue).await.unwrap();
+        let batch = Lane::Batch.queue_name();
+        let envelope = GenerateImageMessage::new(inspiration_image.id).into_envelope();
+        queue
+            .send(batch, &serde_json::to_value(&envelope).unwrap())
+            .await
+            .unwrap();
+
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let storage = Arc::new(LocalStorage::new(
+            std::env::temp_dir().join(format!("generator-worker-test-{}", std::process::id())),
+            "/files".to_string(),
+        ));
+        let processed = process_next_message(
+            &queue,
+            &mut WeightedLanes::from_env(),
+            db,
+            &client,
+            storage.clone(),
+            &GenerationDefaults::default(),
+        )
+        .await;
+        assert_eq!(processed, Processed::Completed);
+        assert!(queue.pending(batch).is_empty());
+        assert_eq!(queue.archived(batch).len(), 1);
+
+        let generated_image = GeneratedImages::new(db)
+            .find_by_idempotency_key(&envelope.payload.idempotency_key())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(generated_image.inspiration_image_id, inspiration_image.id);
+        assert_eq!(generated_image.prompt, "A lighthouse");
+        assert_eq!(generated_image.revised_prompt, "A lighthouse at dusk");
+        let key = generated_image.storage_key.unwrap();
+        assert_eq!(storage.get(&key).await.unwrap(), png);
+        let inspiration_image = InspirationImages::new(db)
+            .find(inspiration_image.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            inspiration_image.generation_status,
+            Some(GenerationStatus::Completed)
+        );
+    }
+
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(width, height)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        png.into_inner()
+    }
+
+    fn test_storage(name: &str) -> Arc<dyn Storage> {
+        Arc::new(LocalStorage::new(
+            std::env::temp_dir().join(format!("generator-{name}-test-{}", std::process::id())),
+            "/files".to_string(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_vision_caption_is_saved_and_reused_on_redelivery() {
+        let database = migrated_database().await;
+        let db = &database.db;
+
+        let png = png_bytes(64, 64);
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "choices": [
+                { "message": { "role": "assistant", "content": "A lighthouse at dusk." } }
+              ]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // The first delivery fails after the caption is written.
+        Mock::given(path("/v1/images/generations"))
+            .respond_with(
+                ResponseTemplate::new(429).set_body_json(open_ai_error_stub("rate_limit_exceeded")),
+            )
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/v1/images/generations"))
+            .and(body_partial_json(json!({ "prompt": "A lighthouse at dusk." })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "created": 1710000000,
+              "data": [{ "b64_json": base64::encode(&png), "revised_prompt": "A lighthouse at dusk, lit up" }]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "object": "list",
+              "data": [{ "object": "embedding", "index": 0, "embedding": vec![0.5; 1536] }],
+              "model": EMBEDDING_MODEL
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/lighthouse.png"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(png.clone()))
+            .mount(&mock_server)
+            .await;
+
+        // Without a description the image is captioned.
+        let inspiration_image = InspirationImages::new(db)
+            .create(NewInspirationImage {
+                source_id: "lighthouse".to_string(),
+                source_url: format!("{}/lighthouse.png", mock_server.uri()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let envelope = GenerateImageMessage::new(inspiration_image.id).into_envelope();
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let storage = test_storage("caption");
+        let defaults = GenerationDefaults::default();
+
+        let error = handle_message(&envelope, db, &client, storage.clone(), &defaults).await;
+        assert!(error.is_err());
+        let captioned = InspirationImages::new(db)
+            .find(inspiration_image.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(captioned.caption.as_deref(), Some("A lighthouse at dusk."));
+
+        handle_message(&envelope, db, &client, storage, &defaults)
+            .await
+            .unwrap();
+        let generated_image = GeneratedImages::new(db)
+            .find_by_idempotency_key(&envelope.payload.idempotency_key())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(generated_image.prompt, "A lighthouse at dusk.");
+        assert_eq!(generated_image.generation_source.as_deref(), Some("vision"));
+    }
+
+    #[tokio::test]
+    async fn test_inspiration_derivatives_are_made_from_the_archived_copy() {
+        let database = migrated_database().await;
+        let db = &database.db;
+
+        let png = png_bytes(64, 64);
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/v1/images/generations"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "created": 1710000000,
+              "data": [{ "b64_json": base64::encode(&png), "revised_prompt": "A lighthouse at dusk" }]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "object": "list",
+              "data": [{ "object": "embedding", "index": 0, "embedding": vec![0.5; 1536] }],
+              "model": EMBEDDING_MODEL
+            })))
+            .mount(&mock_server)
+            .await;
+        // The photo has been removed upstream.
+        Mock::given(path("/lighthouse.png"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let storage = test_storage("archived");
+        storage
+            .put("inspiration/lighthouse.png", png.clone(), "image/png")
+            .await
+            .unwrap();
+        let inspiration_image = InspirationImages::new(db)
+            .create(NewInspirationImage {
+                source_id: "lighthouse".to_string(),
+                source_url: format!("{}/lighthouse.png", mock_server.uri()),
+                description: Some("A lighthouse".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        InspirationImageActiveModel {
+            id: Set(inspiration_image.id),
+            archived_key: Set(Some("inspiration/lighthouse.png".to_string())),
+            ..Default::default()
+        }
+        .update(db)
+        .await
+        .unwrap();
+
+        let envelope = GenerateImageMessage::new(inspiration_image.id).into_envelope();
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        handle_message(
+            &envelope,
+            db,
+            &client,
+            storage,
+            &GenerationDefaults::default(),
+        )
+        .await
+        .unwrap();
+
+        let derivatives = ImageDerivatives::new(db)
+            .for_image(ImageKind::Inspiration, inspiration_image.id)
+            .await
+            .unwrap();
+        assert!(!derivatives.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_variations_are_not_drift_analysed_or_embedded() {
+        let database = migrated_database().await;
+        let db = &database.db;
+
+        let png = png_bytes(64, 48);
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/v1/images/variations"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "created": 1710000000,
+              "data": [{ "b64_json": base64::encode(&png) }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/lighthouse.png"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(png.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let inspiration_image = InspirationImages::new(db)
+            .create(NewInspirationImage {
+                source_id: "lighthouse".to_string(),
+                source_url: format!("{}/lighthouse.png", mock_server.uri()),
+                description: Some("A lighthouse".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut message = GenerateImageMessage::new(inspiration_image.id);
+        message.parameters.source = Some("variation".to_string());
+        let envelope = message.into_envelope();
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+
+        handle_message(
+            &envelope,
+            db,
+            &client,
+            test_storage("variation"),
+            &GenerationDefaults::default(),
+        )
+        .await
+        .unwrap();
+        let generated_image = GeneratedImages::new(db)
+            .find_by_idempotency_key(&envelope.payload.idempotency_key())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(generated_image.prompt, "A lighthouse");
+        assert_eq!(generated_image.revised_prompt, "");
+        assert_eq!(generated_image.prompt_similarity, None);
+        assert_eq!(generated_image.added_concepts, None);
+    }
+
+    fn open_ai_error_stub(code: &str) -> serde_json::Value {
+        json!({
+          "error": {
+            "code": code,
+            "message": "Your request was rejected.",
+            "param": null,
+            "type": "invalid_request_error"
+          }
+        })
+    }
+
+    fn default_options() -> ImageOptions {
+        GenerationDefaults::default().resolve(&GenerationParameters::default(), None)
+    }
+
+    fn open_ai_image_stub() -> serde_json::Value {
+        json!({
+          "created": 1710000000,
+          "data": [
+            {
+              "b64_json": "aW1hZ2U=",
+              "revised_prompt": "A knife on a wooden table"
+            }
+          ]
+        })
+    }
+
+    #[test]
+    fn test_image_cost_uses_price_table() {
+        let client = OpenAiClient::new("http://127.0.0.1:1".to_string(), "key".to_string());
+        assert_eq!(
+            client.image_cost(&default_options()),
+            (
+                PriceKey::new("openai", "dall-e-3", DEFAULT_IMAGE_SIZE, "standard"),
+                40_000
+            )
+        );
+
+        let prices = PriceTable::default()
+            .with_overrides("openai/dall-e-3/1024x1024/standard=0.01")
+            .unwrap();
+        let client = client.with_prices(prices);
+        assert_eq!(client.image_cost(&default_options()).1, 10_000);
+    }
+
+    #[tokio::test]
+    async fn test_budget_is_only_checked_when_capped() {
+        let db = DatabaseConnection::Disconnected;
+        assert_eq!(
+            budget_exceeded(&db, &Budget::default()).await.unwrap(),
+            None
+        );
+
+        // A capped budget reads the spend, which fails without a database,
+        // so the worker pauses instead of spending blind.
+        let budget = Budget {
+            daily_micros: Some(1_000_000),
+            monthly_micros: None,
+        };
+        assert!(budget_exceeded(&db, &budget).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generate_image_sends_size_quality_and_style() {
+        let mock_server = MockServer::start().await;
+        Mock::given(body_partial_json(json!({
+            "model": "dall-e-3",
+            "size": "1792x1024",
+            "quality": "hd",
+            "style": "natural",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(open_ai_image_stub()))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let options = ImageOptions {
+            size: "1792x1024".to_string(),
+            quality: "hd".to_string(),
+            style: "natural".to_string(),
+        };
+        client
+            .generate_image("a lighthouse", &options)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_generation_defaults_resolve_message_parameters() {
+        let env: std::collections::HashMap<&str, &str> =
+            [("IMAGE_SIZE", AUTO_SIZE), ("IMAGE_QUALITY", "hd")].into();
+        let defaults =
+            GenerationDefaults::from_lookup(|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(defaults.style, "vivid");
+        assert!(GenerationDefaults::from_lookup(|_| Some("huge".to_string())).is_err());
+
+        let landscape = defaults.resolve(&GenerationParameters::default(), Some((4000, 2250)));
+        assert_eq!(
+            landscape,
+            ImageOptions {
+                size: "1792x1024".to_string(),
+                quality: "hd".to_string(),
+                style: "vivid".to_string(),
+            }
+        );
+        let portrait = defaults.resolve(&GenerationParameters::default(), Some((2000, 3000)));
+        assert_eq!(portrait.size, "1024x1792");
+        let square = defaults.resolve(&GenerationParameters::default(), Some((1100, 1000)));
+        assert_eq!(square.size, DEFAULT_IMAGE_SIZE);
+        let unknown = defaults.resolve(&GenerationParameters::default(), None);
+        assert_eq!(unknown.size, DEFAULT_IMAGE_SIZE);
+
+        let overridden = defaults.resolve(
+            &GenerationParameters {
+                size: Some("1024x1792".to_string()),
+                quality: Some("standard".to_string()),
+                style: Some("natural".to_string()),
+                ..Default::default()
+            },
+            Some((4000, 2250)),
+        );
+        assert_eq!(
+            (
+                overridden.size.as_str(),
+                overridden.quality.as_str(),
+                overridden.style.as_str()
+            ),
+            ("1024x1792", "standard", "natural")
+        );
+    }
+
+    #[test]
+    fn test_backend_capabilities_validate_parameters() {
+        let open_ai = capabilities("openai").unwrap();
+        assert!(capabilities("midjourney").is_none());
+        assert!(open_ai
+            .validate(Some(AUTO_SIZE), Some("hd"), Some("natural"), Some("vision"))
+            .is_ok());
+        assert!(open_ai.validate(None, None, None, None).is_ok());
+        assert!(open_ai.validate(Some("512x512"), None, None, None).is_err());
+        assert!(open_ai.validate(None, Some("ultra"), None, None).is_err());
+        assert!(open_ai.validate(None, None, Some("sepia"), None).is_err());
+        assert!(open_ai
+            .validate(None, None, None, Some("prompt_override"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_generation_source_falls_back_to_vision_without_description() {
+        let defaults = GenerationDefaults::default();
+        let parameters = GenerationParameters::default();
+        assert_eq!(
+            defaults.source(&parameters, true),
+            GenerationSource::Description
+        );
+        assert_eq!(
+            defaults.source(&parameters, false),
+            GenerationSource::Vision
+        );
+
+        let variation = GenerationParameters {
+            source: Some("variation".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            defaults.source(&variation, false),
+            GenerationSource::Variation
+        );
+        let overridden = GenerationParameters {
+            prompt_override: Some("a lighthouse".to_string()),
+            ..variation
+        };
+        assert_eq!(
+            defaults.source(&overridden, false),
+            GenerationSource::PromptOverride
+        );
+
+        let env: std::collections::HashMap<&str, &str> = [("GENERATION_SOURCE", "vision")].into();
+        let defaults =
+            GenerationDefaults::from_lookup(|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(defaults.source(&parameters, true), GenerationSource::Vision);
+        assert!(GenerationDefaults::from_lookup(|name| {
+            (name == "GENERATION_SOURCE").then(|| "sketch".to_string())
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_variation_options_use_square_sizes() {
+        let options = ImageOptions {
+            size: "1792x1024".to_string(),
+            quality: "hd".to_string(),
+            style: "natural".to_string(),
+        }
+        .for_variation();
+        assert_eq!(
+            (options.size.as_str(), options.quality.as_str()),
+            ("1024x1024", "standard")
+        );
+        let small = ImageOptions {
+            size: "512x512".to_string(),
+            ..options
+        }
+        .for_variation();
+        assert_eq!(small.size, "512x512");
+
+        let client = OpenAiClient::new("http://127.0.0.1:1".to_string(), "key".to_string());
+        assert_eq!(
+            client.variation_cost(&small),
+            (
+                PriceKey::new("openai", "dall-e-2", "512x512", "standard"),
+                18_000
+            )
+        );
+        assert_eq!(client.caption_cost().1, 5_000);
+    }
+
+    #[test]
+    fn test_variation_png_crops_to_a_centred_square() {
+        let source = image::DynamicImage::new_rgb8(300, 200);
+        let mut encoded = Cursor::new(Vec::new());
+        source
+            .write_to(&mut encoded, image::ImageOutputFormat::Jpeg(85))
+            .unwrap();
+
+        let png = variation_png(&encoded.into_inner(), 256).unwrap();
+        assert_eq!(image::guess_format(&png).unwrap(), image::ImageFormat::Png);
+        let variation = image::load_from_memory(&png).unwrap();
+        assert_eq!((variation.width(), variation.height()), (256, 256));
+        assert!(matches!(variation, image::DynamicImage::ImageRgb8(_)));
+    }
+
+    #[tokio::test]
+    async fn test_caption_image_sends_image_inline() {
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/v1/chat/completions"))
+            .and(body_partial_json(json!({ "model": "gpt-4o" })))
+            .and(body_string_contains("data:image/jpeg;base64,aW1hZ2U="))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "choices": [
+                { "message": { "role": "assistant", "content": " A lighthouse at dusk. " } }
+              ]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let caption = client.caption_image(b"image", "image/jpeg").await.unwrap();
+        assert_eq!(caption, "A lighthouse at dusk.");
+    }
+
+    #[tokio::test]
+    async fn test_caption_image_without_content_is_permanent() {
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "choices": [{ "message": { "role": "assistant", "content": null } }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let result = client.caption_image(b"image", "image/jpeg").await;
+        assert!(matches!(result, Err(GenerationError::Permanent(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_variation_uploads_image_as_multipart() {
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/v1/images/variations"))
+            .and(body_string_contains("dall-e-2"))
+            .and(body_string_contains("filename=\"image.png\""))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+              "created": 1710000000,
+              "data": [{ "b64_json": "aW1hZ2U=" }]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenAiClient::new(mock_server.uri(), "key".to_string());
+        let options = default_options().for_variation();
+        let response = client
+            .create_variation(b"png".to_vec(), &options)
+            .await
+            .unwrap();
+        assert_eq!(response.data[0].revised_prompt, "");
+    }
+}
@@ -0,0 +1,181 @@
+//! Admin pages and actions. They are only served when `ADMIN_TOKEN` is set,
+//! to requests that send it as a bearer token or as the password of HTTP
+//! basic auth, which lets a browser prompt for it.
+
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{get, post, web, FromRequest, HttpRequest};
+use database::entity::sea_orm_active_enums::{ImageKind, TagSource};
+use database::pool::Databases;
+use database::repository::Pairs;
+use database::tags::{normalize_tag, tag_image, tags_for_image, untag_image};
+use sea_orm::{ActiveEnum, DatabaseConnection};
+
+use crate::error::AppError;
+use crate::extractors::ImageId;
+use crate::template::{AdminImageTagsTemplate, TagEditorTemplate};
+
+#[derive(Debug, Clone, Default)]
+pub struct AdminToken(Option<String>);
+
+impl AdminToken {
+    pub fn new(token: Option<String>) -> Self {
+        Self(token.filter(|token| !token.is_empty()))
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(std::env::var("ADMIN_TOKEN").ok())
+    }
+
+    fn accepts(&self, req: &HttpRequest) -> Result<(), AppError> {
+        let Some(token) = &self.0 else {
+            return Err(AppError::not_found("Admin pages are disabled"));
+        };
+        let credentials = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(credentials);
+        match credentials {
+            Some(credentials) if constant_time_eq(credentials.as_bytes(), token.as_bytes()) => {
+                Ok(())
+            }
+            _ => Err(AppError::unauthorized("Admin token required")),
+        }
+    }
+}
+
+/// The token from a `Bearer` header, or the password from a `Basic` one.
+fn credentials(authorization: &str) -> Option<String> {
+    if let Some(token) = authorization.strip_prefix("Bearer ") {
+        return Some(token.trim().to_string());
+    }
+    let encoded = authorization.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(base64::decode(encoded.trim()).ok()?).ok()?;
+    let (_, password) = decoded.split_once(':')?;
+    Some(password.to_string())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Taking this as a handler argument limits the handler to admins.
+#[derive(Debug, Clone, Copy)]
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = AppError;
+    type Future = Ready<Result<Self, AppError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let token = req
+            .app_data::<web::Data<AdminToken>>()
+            .map(|token| token.get_ref().clone())
+            .unwrap_or_default();
+        ready(token.accepts(req).map(|_| Admin))
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct TagForm {
+    /// `generated` or `inspiration`, which image of the pair to tag.
+    pub kind: String,
+    pub tag: String,
+}
+
+/// The image of the pair that `kind` names.
+async fn tagged_image(
+    db: &DatabaseConnection,
+    generated_image_id: i32,
+    kind: &str,
+) -> Result<(ImageKind, i32), AppError> {
+    let kind = ImageKind::try_from_value(&kind.to_string())
+        .map_err(|_| AppError::bad_request("Unknown image kind"))?;
+    let pair = Pairs::new(db)
+        .find(generated_image_id)
+        .await
+        .map_err(AppError::internal("Error reading image from db"))?
+        .ok_or_else(|| AppError::not_found("Image not found"))?;
+    let image_id = match kind {
+        ImageKind::Generated => pair.generated_image.id,
+        ImageKind::Inspiration => pair.inspiration_image.id,
+    };
+    Ok((kind, image_id))
+}
+
+async fn tag_editor(
+    db: &DatabaseConnection,
+    generated_image_id: i32,
+    kind: ImageKind,
+    image_id: i32,
+) -> Result<TagEditorTemplate, AppError> {
+    let tags = tags_for_image(db, kind, image_id)
+        .await
+        .map_err(AppError::internal("Error reading tags from db"))?
+        .into_iter()
+        .map(|tag| tag.name)
+        .collect();
+    Ok(TagEditorTemplate {
+        generated_image_id,
+        kind: kind.to_value(),
+        tags,
+    })
+}
+
+#[get("/admin/images/{id}/tags")]
+pub async fn get_image_tags(
+    _admin: Admin,
+    databases: web::Data<Databases>,
+    ImageId(id): ImageId,
+) -> Result<AdminImageTagsTemplate, AppError> {
+    // Read from the primary so the page reflects edits straight away.
+    let db = databases.primary();
+    let pair = Pairs::new(db)
+        .find(id)
+        .await
+        .map_err(AppError::internal("Error reading image from db"))?
+        .ok_or_else(|| AppError::not_found("Image not found"))?;
+
+    Ok(AdminImageTagsTemplate {
+        inspiration_tags: tag_editor(db, id, ImageKind::Inspiration, pair.inspiration_image.id)
+            .await?,
+        generated_tags: tag_editor(db, id, ImageKind::Generated, id).await?,
+        pair,
+    })
+}
+
+#[post("/admin/images/{id}/tags")]
+pub async fn add_image_tag(
+    _admin: Admin,
+    databases: web::Data<Databases>,
+    ImageId(id): ImageId,
+    form: web::Form<TagForm>,
+) -> Result<TagEditorTemplate, AppError> {
+    let db = databases.primary();
+    let (kind, image_id) = tagged_image(db, id, &form.kind).await?;
+    if normalize_tag(&form.tag).is_none() {
+        return Err(AppError::bad_request("Invalid tag"));
+    }
+    tag_image(db, kind, image_id, &form.tag, TagSource::Manual)
+        .await
+        .map_err(AppError::internal("Error saving tag"))?;
+    tag_editor(db, id, kind, image_id).await
+}
+
+#[post("/admin/images/{id}/tags/delete")]
+pub async fn delete_image_tag(
+    _admin: Admin,
+    databases: web::Data<Databases>,
+    ImageId(id): ImageId,
+    form: web::Form<TagForm>,
+) -> Result<TagEditorTemplate, AppError> {
+    let db = databases.primary();
+    let (kind, image_id) = tagged_image(db, id, &form.kind).await?;
+    untag_image(db, kind, image_id, &form.tag)
+        .await
+        .map_err(AppError::internal("Error removing tag"))?;
+    tag_editor(db, id, kind, image_id).await
+}
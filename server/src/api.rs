@@ -1,155 +1,123 @@
-use actix_web::{error::InternalError, get, web};
-use database::entity::generated_image::{Entity as GeneratedImage, Model as GeneratedImageModel};
-use database::entity::inspiration_image::{
-    Entity as InspirationImage, Model as InspirationImageModel,
-};
-use sea_orm::{DatabaseConnection, DbBackend, DbErr, EntityTrait, Statement};
-
-use crate::template::GeneratedImageTemplate;
+use actix_web::{get, web};
+use database::entity::image_derivative::Model as ImageDerivativeModel;
+use database::entity::sea_orm_active_enums::ImageKind;
+use database::prompt_drift;
+use database::repository::{ImageDerivatives, Pair, Pairs};
+use database::tags::tags_for_image;
+use sea_orm::{DatabaseConnection, DbErr};
+
+use crate::error::AppError;
+use crate::extractors::ImageId;
+use crate::template::{GeneratedImageTemplate, Srcset};
 
 #[get("/images/{id}")]
 pub async fn get_image_by_id(
     db: web::Data<DatabaseConnection>,
-    id: web::Path<String>,
-) -> askama::Result<GeneratedImageTemplate, InternalError<String>> {
-    let id = id.parse::<i32>();
-    if id.is_err() {
-        return Err(InternalError::new(
-            "Invalid ID".to_string(),
-            actix_web::http::StatusCode::from_u16(400).unwrap(),
-        ));
-    }
-
-    let image = GeneratedImage::find_by_id(id.unwrap())
-        .one(db.as_ref())
-        .await;
-
-    let (generated_image, inspiration_image) =
-        get_generated_and_inspiration_image(image, db.as_ref()).await?;
-
-    Ok(GeneratedImageTemplate {
-        generated_image,
-        inspiration_image,
-    })
+    ImageId(id): ImageId,
+) -> Result<GeneratedImageTemplate, AppError> {
+    let pair = Pairs::new(db.as_ref()).find(id).await;
+    build_image_template(pair, db.as_ref()).await
 }
 
 #[get("/images/{id}/next")]
 pub async fn get_next_image(
     db: web::Data<DatabaseConnection>,
-    id: web::Path<String>,
-) -> askama::Result<GeneratedImageTemplate, InternalError<String>> {
-    let id = id.parse::<i32>();
-    if id.is_err() {
-        return Err(InternalError::new(
-            "Invalid ID".to_string(),
-            actix_web::http::StatusCode::from_u16(400).unwrap(),
-        ));
-    }
-
-    let image = GeneratedImage::find()
-        .from_raw_sql(Statement::from_sql_and_values(
-            DbBackend::Postgres,
-            r#"SELECT * FROM generated_image WHERE id > $1 ORDER BY id LIMIT 1"#,
-            [id.unwrap().into()],
-        ))
-        .one(db.as_ref())
-        .await;
-    let (generated_image, inspiration_image) =
-        get_generated_and_inspiration_image(image, db.as_ref()).await?;
-
-    Ok(GeneratedImageTemplate {
-        generated_image,
-        inspiration_image,
-    })
+    ImageId(id): ImageId,
+) -> Result<GeneratedImageTemplate, AppError> {
+    let pair = Pairs::new(db.as_ref()).next(id).await;
+    build_image_template(pair, db.as_ref()).await
 }
 
 #[get("/images/{id}/previous")]
 pub async fn get_previous_image(
     db: web::Data<DatabaseConnection>,
-    id: web::Path<String>,
-) -> askama::Result<GeneratedImageTemplate, InternalError<String>> {
-    let id = id.parse::<i32>();
-    if id.is_err() {
-        return Err(InternalError::new(
-            "Invalid ID".to_string(),
-            actix_web::http::StatusCode::from_u16(400).unwrap(),
-        ));
-    }
-
-    let image = GeneratedImage::find()
-        .from_raw_sql(Statement::from_sql_and_values(
-            DbBackend::Postgres,
-            r#"SELECT * FROM generated_image WHERE id < $1 ORDER BY id DESC LIMIT 1"#,
-            [id.unwrap().into()],
-        ))
-        .one(db.as_ref())
-        .await;
-    let (generated_image, inspiration_image) =
-        get_generated_and_inspiration_image(image, db.as_ref()).await?;
-
-    Ok(GeneratedImageTemplate {
-        generated_image,
-        inspiration_image,
-    })
+    ImageId(id): ImageId,
+) -> Result<GeneratedImageTemplate, AppError> {
+    let pair = Pairs::new(db.as_ref()).previous(id).await;
+    build_image_template(pair, db.as_ref()).await
 }
 
 #[get("/images/first")]
 pub async fn get_first_image(
     db: web::Data<DatabaseConnection>,
-) -> askama::Result<GeneratedImageTemplate, InternalError<String>> {
-    let image = GeneratedImage::find().one(db.as_ref()).await;
-    let (generated_image, inspiration_image) =
-        get_generated_and_inspiration_image(image, db.as_ref()).await?;
-
-    Ok(GeneratedImageTemplate {
-        generated_image,
-        inspiration_image,
-    })
+) -> Result<GeneratedImageTemplate, AppError> {
+    let pair = Pairs::new(db.as_ref()).first().await;
+    build_image_template(pair, db.as_ref()).await
 }
 
-async fn get_generated_and_inspiration_image(
-    image: Result<Option<GeneratedImageModel>, DbErr>,
+async fn get_derivatives(
+    image_kind: ImageKind,
+    image_id: i32,
     db: &DatabaseConnection,
-) -> Result<(GeneratedImageModel, InspirationImageModel), InternalError<String>> {
-    let image = match image {
-        Ok(image) => image,
-        Err(_) => {
-            return Err(InternalError::new(
-                "Error reading image from db".to_string(),
-                actix_web::http::StatusCode::from_u16(500).unwrap(),
-            ));
-        }
-    };
-
-    let image = match image {
-        Some(image) => image,
-        None => {
-            return Err(InternalError::new(
-                "Image not found".to_string(),
-                actix_web::http::StatusCode::from_u16(404).unwrap(),
-            ));
-        }
-    };
+) -> Result<Vec<ImageDerivativeModel>, AppError> {
+    ImageDerivatives::new(db)
+        .for_image(image_kind, image_id)
+        .await
+        .map_err(AppError::internal("Error reading image from db"))
+}
 
-    let inspiration_image = InspirationImage::find_by_id(image.inspiration_image_id)
-        .one(db)
-        .await;
+async fn get_pair_tags(
+    generated_image_id: i32,
+    inspiration_image_id: i32,
+    db: &DatabaseConnection,
+) -> Result<Vec<String>, AppError> {
+    let mut tags: Vec<String> = tags_for_image(db, ImageKind::Inspiration, inspiration_image_id)
+        .await
+        .map_err(AppError::internal("Error reading tags from db"))?
+        .into_iter()
+        .chain(
+            tags_for_image(db, ImageKind::Generated, generated_image_id)
+                .await
+                .map_err(AppError::internal("Error reading tags from db"))?,
+        )
+        .map(|tag| tag.name)
+        .collect();
+    tags.sort();
+    tags.dedup();
+    Ok(tags)
+}
 
-    let inspiration_image = match inspiration_image {
-        Ok(inspiration_image) => inspiration_image,
-        Err(_) => {
-            return Err(InternalError::new(
-                "Error reading image from db".to_string(),
-                actix_web::http::StatusCode::from_u16(500).unwrap(),
-            ));
-        }
+async fn build_image_template(
+    pair: Result<Option<Pair>, DbErr>,
+    db: &DatabaseConnection,
+) -> Result<GeneratedImageTemplate, AppError> {
+    let Pair {
+        generated_image,
+        inspiration_image,
+    } = pair
+        .map_err(AppError::internal("Error reading image from db"))?
+        .ok_or_else(|| AppError::not_found("Image not found"))?;
+    let generated_derivatives =
+        get_derivatives(ImageKind::Generated, generated_image.id, db).await?;
+    let inspiration_derivatives =
+        get_derivatives(ImageKind::Inspiration, inspiration_image.id, db).await?;
+
+    let generated_srcset = Srcset::from_derivatives(
+        &format!("/media/generated/{}", generated_image.id),
+        &generated_derivatives,
+    );
+    let inspiration_srcset = Srcset::from_derivatives(
+        &format!("/media/inspiration/{}", inspiration_image.id),
+        &inspiration_derivatives,
+    );
+
+    // Variations have no revised prompt, so there is nothing to compare.
+    let prompt_diff = if prompt_drift::is_prompted(
+        &generated_image.revised_prompt,
+        generated_image.generation_source.as_deref(),
+    ) {
+        prompt_drift::diff(&generated_image.prompt, &generated_image.revised_prompt)
+    } else {
+        Vec::new()
     };
+    let tags = get_pair_tags(generated_image.id, inspiration_image.id, db).await?;
 
-    match inspiration_image {
-        Some(inspo_image) => Ok((image, inspo_image)),
-        None => Err(InternalError::new(
-            "Image not found".to_string(),
-            actix_web::http::StatusCode::from_u16(404).unwrap(),
-        )),
-    }
+    Ok(GeneratedImageTemplate {
+        generated_image,
+        inspiration_image,
+        generated_srcset,
+        inspiration_srcset,
+        prompt_diff,
+        tags,
+    })
 }
@@ -0,0 +1,45 @@
+use actix_web::{get, web};
+use database::entity::collection::{Column as CollectionColumn, Entity as Collection};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbBackend, EntityTrait, FromQueryResult, QueryFilter,
+    Statement,
+};
+
+use crate::error::AppError;
+use crate::tags::PairSummary;
+use crate::template::CollectionTemplate;
+
+#[get("/collections/{slug}")]
+pub async fn get_collection(
+    db: web::Data<DatabaseConnection>,
+    slug: web::Path<String>,
+) -> Result<CollectionTemplate, AppError> {
+    let collection = Collection::find()
+        .filter(CollectionColumn::Slug.eq(slug.into_inner()))
+        .one(db.as_ref())
+        .await
+        .map_err(AppError::internal("Error reading collection from db"))?
+        .ok_or_else(|| AppError::not_found("Collection not found"))?;
+
+    let pairs = PairSummary::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT
+            generated_image.id AS generated_image_id,
+            generated_image.inspiration_image_id,
+            generated_image.revised_prompt
+        FROM collection_item
+        JOIN generated_image ON generated_image.id = collection_item.generated_image_id
+        WHERE collection_item.collection_id = $1
+        ORDER BY collection_item.position, collection_item.id"#,
+        [collection.id.into()],
+    ))
+    .all(db.as_ref())
+    .await
+    .map_err(AppError::internal("Error reading collection from db"))?;
+
+    Ok(CollectionTemplate {
+        name: collection.name,
+        description: collection.description,
+        pairs,
+    })
+}
@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use actix_web::{get, web};
+use chrono::{NaiveDate, Utc};
+use database::costs::{
+    current_spend, daily_spend, micros_to_usd, spend_by_price, start_of_month, Budget, PriceTable,
+};
+use prometheus::{Gauge, IntGauge, Registry};
+use sea_orm::{DatabaseConnection, DbErr};
+
+use crate::admin::Admin;
+use crate::error::AppError;
+use crate::template::CostsTemplate;
+
+const METRICS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// How many days of spend the costs page lists.
+const DAILY_SPEND_DAYS: i64 = 30;
+
+/// Generation spend against the budget. Like the reconciler's, the
+/// generator's spend is read back from the database, so the gauges don't
+/// depend on which generator recorded it.
+#[derive(Clone)]
+pub struct CostMetrics {
+    budget: Budget,
+    spend_today: Gauge,
+    spend_month: Gauge,
+    daily_budget: Gauge,
+    monthly_budget: Gauge,
+    budget_exceeded: IntGauge,
+}
+
+impl CostMetrics {
+    pub fn register(registry: &Registry, budget: Budget) -> prometheus::Result<Self> {
+        let gauge = |name: &str, help: &str| -> prometheus::Result<Gauge> {
+            let gauge = Gauge::new(name, help)?;
+            registry.register(Box::new(gauge.clone()))?;
+            Ok(gauge)
+        };
+        let budget_exceeded = IntGauge::new(
+            "generation_budget_exceeded",
+            "1 while generation is paused because a budget is spent",
+        )?;
+        registry.register(Box::new(budget_exceeded.clone()))?;
+
+        Ok(Self {
+            budget,
+            spend_today: gauge(
+                "generation_spend_today_usd",
+                "Generation spend since midnight UTC",
+            )?,
+            spend_month: gauge(
+                "generation_spend_month_usd",
+                "Generation spend since the start of the month",
+            )?,
+            daily_budget: gauge(
+                "generation_budget_daily_usd",
+                "Daily generation budget, 0 when there is no cap",
+            )?,
+            monthly_budget: gauge(
+                "generation_budget_monthly_usd",
+                "Monthly generation budget, 0 when there is no cap",
+            )?,
+            budget_exceeded,
+        })
+    }
+
+    pub async fn refresh(&self, db: &DatabaseConnection) -> Result<(), DbErr> {
+        let spend = current_spend(db, Utc::now()).await?;
+
+        self.spend_today.set(micros_to_usd(spend.today_micros));
+        self.spend_month.set(micros_to_usd(spend.month_micros));
+        self.daily_budget
+            .set(micros_to_usd(self.budget.daily_micros.unwrap_or(0)));
+        self.monthly_budget
+            .set(micros_to_usd(self.budget.monthly_micros.unwrap_or(0)));
+        self.budget_exceeded
+            .set(self.budget.exceeded(&spend).is_some() as i64);
+        Ok(())
+    }
+
+    /// Keeps the gauges up to date for as long as the server runs.
+    pub fn spawn_refresh(self, db: DatabaseConnection) {
+        tokio::spawn(async move {
+            loop {
+                let _ = self.refresh(&db).await;
+                tokio::time::sleep(METRICS_REFRESH_INTERVAL).await;
+            }
+        });
+    }
+}
+
+pub struct DailySpendRow {
+    pub day: NaiveDate,
+    pub generations: i64,
+    pub usd: f64,
+}
+
+/// Spend for one backend, model, size and quality.
+pub struct PriceSpendRow {
+    pub key: String,
+    pub generations: i64,
+    pub usd: f64,
+}
+
+pub struct PriceRow {
+    pub key: String,
+    pub usd: f64,
+}
+
+#[get("/admin/costs")]
+pub async fn get_costs(
+    _admin: Admin,
+    db: web::Data<DatabaseConnection>,
+    budget: web::Data<Budget>,
+    prices: web::Data<PriceTable>,
+) -> Result<CostsTemplate, AppError> {
+    let now = Utc::now();
+    let spend = current_spend(db.as_ref(), now)
+        .await
+        .map_err(AppError::internal("Error loading generation spend"))?;
+    let days = daily_spend(db.as_ref(), now - chrono::Duration::days(DAILY_SPEND_DAYS))
+        .await
+        .map_err(AppError::internal("Error loading generation spend"))?;
+    let by_price = spend_by_price(db.as_ref(), start_of_month(now))
+        .await
+        .map_err(AppError::internal("Error loading generation spend"))?;
+
+    Ok(CostsTemplate {
+        today_usd: micros_to_usd(spend.today_micros),
+        month_usd: micros_to_usd(spend.month_micros),
+        daily_budget_usd: budget.daily_micros.map(micros_to_usd),
+        monthly_budget_usd: budget.monthly_micros.map(micros_to_usd),
+        exceeded: budget.exceeded(&spend).map(|period| period.to_string()),
+        days: days
+            .into_iter()
+            .map(|day| DailySpendRow {
+                day: day.day,
+                generations: day.generations,
+                usd: micros_to_usd(day.cost_micros),
+            })
+            .collect(),
+        month_by_price: by_price
+            .into_iter()
+            .map(|spend| PriceSpendRow {
+                key: format!(
+                    "{}/{}/{}/{}",
+                    spend.backend, spend.model, spend.size, spend.quality
+                ),
+                generations: spend.generations,
+                usd: micros_to_usd(spend.cost_micros),
+            })
+            .collect(),
+        prices: prices
+            .entries()
+            .map(|(key, micros)| PriceRow {
+                key: key.to_string(),
+                usd: micros_to_usd(micros),
+            })
+            .collect(),
+    })
+}
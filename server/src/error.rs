@@ -0,0 +1,187 @@
+use std::fmt::{self, Display};
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use askama_actix::TemplateToResponse;
+use serde::Serialize;
+use tracing::{event, Level};
+
+use crate::template::{ErrorFragmentTemplate, ErrorPageTemplate};
+
+/// Every way a handler can fail. The message is shown to the user, the cause
+/// is only logged.
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    /// Missing or wrong admin credentials.
+    Unauthorized(String),
+    NotFound(String),
+    /// Something the server depends on, such as an upstream image host,
+    /// failed.
+    BadGateway {
+        message: String,
+        cause: String,
+    },
+    Internal {
+        message: String,
+        cause: String,
+    },
+}
+
+impl AppError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        AppError::BadRequest(message.into())
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        AppError::Unauthorized(message.into())
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        AppError::NotFound(message.into())
+    }
+
+    /// For `map_err`, e.g. `.map_err(AppError::internal("Error reading tag"))`.
+    pub fn internal<E: Display>(message: &'static str) -> impl FnOnce(E) -> Self {
+        move |cause| AppError::Internal {
+            message: message.to_string(),
+            cause: cause.to_string(),
+        }
+    }
+
+    pub fn bad_gateway<E: Display>(message: &'static str) -> impl FnOnce(E) -> Self {
+        move |cause| AppError::BadGateway {
+            message: message.to_string(),
+            cause: cause.to_string(),
+        }
+    }
+
+    /// Wraps errors raised by actix itself, such as a query string that
+    /// doesn't deserialize, so they are rendered like ours.
+    pub fn from_actix(error: &actix_web::Error) -> Self {
+        let status = error.as_response_error().status_code();
+        let message = status
+            .canonical_reason()
+            .unwrap_or("Something went wrong")
+            .to_string();
+        match status.as_u16() {
+            404 => AppError::NotFound(message),
+            400..=499 => AppError::BadRequest(error.to_string()),
+            _ => AppError::Internal {
+                message,
+                cause: error.to_string(),
+            },
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::BadRequest(message)
+            | AppError::Unauthorized(message)
+            | AppError::NotFound(message)
+            | AppError::BadGateway { message, .. }
+            | AppError::Internal { message, .. } => message,
+        }
+    }
+
+    pub fn log(&self, request_id: &str) {
+        let status = self.status_code().as_u16();
+        match self {
+            AppError::BadGateway { message, cause } | AppError::Internal { message, cause } => {
+                event!(Level::ERROR, request_id, status, %cause, "{message}")
+            }
+            _ => event!(Level::INFO, request_id, status, "{}", self.message()),
+        }
+    }
+
+    /// JSON for API clients, a fragment for htmx to swap in and a full page
+    /// for everything else.
+    pub fn render(&self, format: ResponseFormat, request_id: &str) -> HttpResponse {
+        let status = self.status_code();
+        let mut response = match format {
+            ResponseFormat::Json => HttpResponse::build(status).json(ErrorBody {
+                error: self.message(),
+                status: status.as_u16(),
+                request_id,
+            }),
+            ResponseFormat::Fragment => ErrorFragmentTemplate {
+                status: status.as_u16(),
+                message: self.message().to_string(),
+                request_id: request_id.to_string(),
+            }
+            .to_response(),
+            ResponseFormat::Page => ErrorPageTemplate {
+                status: status.as_u16(),
+                message: self.message().to_string(),
+                request_id: request_id.to_string(),
+            }
+            .to_response(),
+        };
+        *response.status_mut() = status;
+        if let AppError::Unauthorized(_) = self {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Basic realm=\"admin\""),
+            );
+        }
+        response
+    }
+}
+
+impl Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadGateway { .. } => StatusCode::BAD_GATEWAY,
+            AppError::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Replaced by [`crate::middleware::negotiate_errors`] once the request is
+    /// known.
+    fn error_response(&self) -> HttpResponse {
+        self.render(ResponseFormat::Page, "")
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+    status: u16,
+    request_id: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Json,
+    Fragment,
+    Page,
+}
+
+impl ResponseFormat {
+    /// htmx requests get a fragment even if they accept JSON.
+    pub fn of(req: &HttpRequest) -> Self {
+        if req.headers().contains_key("HX-Request") {
+            return ResponseFormat::Fragment;
+        }
+        let accepts_json = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .map(|accept| accept.contains("application/json"))
+            .unwrap_or(false);
+        if accepts_json {
+            ResponseFormat::Json
+        } else {
+            ResponseFormat::Page
+        }
+    }
+}
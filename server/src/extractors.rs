@@ -0,0 +1,44 @@
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use database::entity::sea_orm_active_enums::ImageKind;
+
+use crate::error::AppError;
+
+/// The `{id}` path segment of image routes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageId(pub i32);
+
+impl FromRequest for ImageId {
+    type Error = AppError;
+    type Future = Ready<Result<Self, AppError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.match_info()
+                .get("id")
+                .and_then(|id| id.parse().ok())
+                .map(ImageId)
+                .ok_or_else(|| AppError::bad_request("Invalid ID")),
+        )
+    }
+}
+
+/// The `{kind}` path segment of media routes, `generated` or `inspiration`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaKind(pub ImageKind);
+
+impl FromRequest for MediaKind {
+    type Error = AppError;
+    type Future = Ready<Result<Self, AppError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let kind = match req.match_info().get("kind") {
+            Some("generated") => Ok(MediaKind(ImageKind::Generated)),
+            Some("inspiration") => Ok(MediaKind(ImageKind::Inspiration)),
+            _ => Err(AppError::not_found("Unknown media kind")),
+        };
+        ready(kind)
+    }
+}
@@ -0,0 +1,52 @@
+use actix_web::http::header::{HeaderValue, CACHE_CONTROL};
+use actix_web::{get, web, HttpResponse};
+use storage::Storage;
+
+use crate::error::AppError;
+
+/// Images and their derivatives, the only objects `/files` serves. Anything
+/// else in storage, such as dataset exports, stays private.
+const SERVED_PREFIXES: [&str; 4] = [
+    "inspiration/",
+    "generated/",
+    "derivatives/inspiration/",
+    "derivatives/generated/",
+];
+
+/// Serves images from local storage at `/files`, its default
+/// `STORAGE_PUBLIC_URL`, so the urls it hands out resolve. Other backends
+/// serve their own urls.
+#[get("/files/{key:.*}")]
+pub async fn get_file(
+    storage: web::Data<dyn Storage>,
+    key: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let key = key.into_inner();
+    let served = SERVED_PREFIXES.iter().any(|prefix| key.starts_with(prefix));
+    if !storage.is_local() || !served {
+        return Err(AppError::not_found("File not found"));
+    }
+    let exists = storage
+        .exists(&key)
+        .await
+        .map_err(|_| AppError::not_found("File not found"))?;
+    if !exists {
+        return Err(AppError::not_found("File not found"));
+    }
+    let data = storage
+        .get(&key)
+        .await
+        .map_err(AppError::internal("Error reading file"))?;
+
+    let extension = key.rsplit_once('.').map(|(_, extension)| extension);
+    let content_type = extension
+        .map(actix_files::file_extension_to_mime)
+        .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            CACHE_CONTROL,
+            HeaderValue::from_static("public, max-age=86400"),
+        ))
+        .body(data))
+}
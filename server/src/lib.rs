@@ -1,3 +1,16 @@
+pub mod admin;
 pub mod api;
+pub mod collections;
+pub mod costs;
+pub mod error;
+pub mod extractors;
+pub mod files;
+pub mod media;
+pub mod middleware;
+pub mod reconciliation;
+pub mod reports;
+pub mod search;
+pub mod similar;
 pub mod startup;
+pub mod tags;
 pub mod template;
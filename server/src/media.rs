@@ -0,0 +1,339 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use actix_files::NamedFile;
+use actix_web::http::header::{HeaderValue, CACHE_CONTROL};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use database::entity::generated_image::Entity as GeneratedImage;
+use database::entity::inspiration_image::Entity as InspirationImage;
+use database::entity::sea_orm_active_enums::ImageKind;
+use database::repository::ImageDerivatives;
+use image::imageops::FilterType;
+use image::ImageOutputFormat;
+use lru::LruCache;
+use sea_orm::{ActiveEnum, DatabaseConnection, EntityTrait};
+use sha2::{Digest, Sha256};
+use storage::Storage;
+
+use crate::error::AppError;
+use crate::extractors::{ImageId, MediaKind};
+
+const MAX_WIDTH: u32 = 2048;
+
+#[derive(Debug, Clone)]
+pub struct CachedMedia {
+    pub path: PathBuf,
+    pub content_type: String,
+    size: u64,
+}
+
+struct MediaCacheIndex {
+    entries: LruCache<String, CachedMedia>,
+    total_bytes: u64,
+}
+
+/// An on-disk cache of media responses, evicting the least recently used
+/// files once `max_bytes` is exceeded. The index lives in memory, so the
+/// files it wrote are cleared whenever the cache is created.
+pub struct MediaCache {
+    root: PathBuf,
+    max_bytes: u64,
+    index: Mutex<MediaCacheIndex>,
+    writes: AtomicU64,
+}
+
+impl MediaCache {
+    pub fn new(root: PathBuf, max_bytes: u64) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&root)?;
+        // `MEDIA_CACHE_PATH` may point somewhere shared, so only files named
+        // the way `insert` names them are removed.
+        for entry in std::fs::read_dir(&root)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && is_cache_file(&entry.file_name().to_string_lossy()) {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+
+        Ok(Self {
+            root,
+            max_bytes,
+            index: Mutex::new(MediaCacheIndex {
+                entries: LruCache::unbounded(),
+                total_bytes: 0,
+            }),
+            writes: AtomicU64::new(0),
+        })
+    }
+
+    pub fn from_env() -> std::io::Result<Self> {
+        let root = std::env::var("MEDIA_CACHE_PATH")
+            .map(PathBuf::from)
+            .unwrap_or(std::env::temp_dir().join("media-cache"));
+        let max_bytes = std::env::var("MEDIA_CACHE_MAX_BYTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(512 * 1024 * 1024);
+        Self::new(root, max_bytes)
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedMedia> {
+        let mut index = self.index.lock().unwrap();
+        index.entries.get(key).cloned()
+    }
+
+    pub async fn insert(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        content_type: String,
+    ) -> std::io::Result<CachedMedia> {
+        let file_name = hex::encode(Sha256::digest(key.as_bytes()));
+        let path = self.root.join(&file_name);
+        // Write to a unique temporary file and rename it into place so
+        // concurrent requests never serve a partially written file.
+        let temp_path = self.root.join(format!(
+            "{}.{}.tmp",
+            file_name,
+            self.writes.fetch_add(1, Ordering::Relaxed)
+        ));
+        let size = data.len() as u64;
+        tokio::fs::write(&temp_path, data).await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+
+        let media = CachedMedia {
+            path,
+            content_type,
+            size,
+        };
+
+        let mut index = self.index.lock().unwrap();
+        if let Some(previous) = index.entries.put(key.to_string(), media.clone()) {
+            index.total_bytes -= previous.size;
+        }
+        index.total_bytes += size;
+        while index.total_bytes > self.max_bytes && index.entries.len() > 1 {
+            match index.entries.pop_lru() {
+                Some((_, evicted)) => {
+                    index.total_bytes -= evicted.size;
+                    let _ = std::fs::remove_file(&evicted.path);
+                }
+                None => break,
+            }
+        }
+
+        Ok(media)
+    }
+}
+
+/// A sha256 hex digest, optionally followed by the `.{n}.tmp` of a write that
+/// never finished.
+fn is_cache_file(name: &str) -> bool {
+    let (digest, rest) = name.split_at(name.len().min(64));
+    let is_digest = digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit());
+    let is_temp = rest.is_empty()
+        || rest
+            .strip_prefix('.')
+            .and_then(|rest| rest.strip_suffix(".tmp"))
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+    is_digest && is_temp
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct MediaQuery {
+    pub w: Option<u32>,
+    pub format: Option<String>,
+}
+
+fn output_format(format: &str) -> Option<(ImageOutputFormat, &'static str)> {
+    match format {
+        "webp" => Some((ImageOutputFormat::WebP, "image/webp")),
+        "png" => Some((ImageOutputFormat::Png, "image/png")),
+        "jpeg" | "jpg" => Some((ImageOutputFormat::Jpeg(85), "image/jpeg")),
+        _ => None,
+    }
+}
+
+async fn fetch_bytes(
+    storage: &dyn Storage,
+    key: Option<&str>,
+    url: &str,
+) -> anyhow::Result<Vec<u8>> {
+    match key {
+        Some(key) => storage.get(key).await,
+        None => Ok(reqwest::get(url)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec()),
+    }
+}
+
+/// Loads the original bytes for an image, preferring the copy in storage and
+/// falling back to the upstream url.
+async fn fetch_original(
+    db: &DatabaseConnection,
+    storage: &dyn Storage,
+    kind: ImageKind,
+    id: i32,
+) -> Result<Vec<u8>, AppError> {
+    let (key, url) = match kind {
+        ImageKind::Generated => GeneratedImage::find_by_id(id)
+            .one(db)
+            .await
+            .map_err(AppError::internal("Error reading image from db"))?
+            .map(|image| (image.storage_key, image.source_url)),
+        ImageKind::Inspiration => InspirationImage::find_by_id(id)
+            .one(db)
+            .await
+            .map_err(AppError::internal("Error reading image from db"))?
+            .map(|image| (image.archived_key, image.source_url)),
+    }
+    .ok_or_else(|| AppError::not_found("Image not found"))?;
+
+    fetch_bytes(storage, key.as_deref(), &url)
+        .await
+        .map_err(AppError::bad_gateway("Error loading image"))
+}
+
+/// Looks for a stored derivative that already matches the requested width and
+/// format so it doesn't need to be resized again.
+async fn fetch_derivative(
+    db: &DatabaseConnection,
+    storage: &dyn Storage,
+    kind: ImageKind,
+    id: i32,
+    width: u32,
+    format: &str,
+) -> Option<(Vec<u8>, String)> {
+    let derivative = ImageDerivatives::new(db)
+        .find(kind, id, width as i32, format)
+        .await
+        .ok()??;
+    let data = storage.get(&derivative.storage_key).await.ok()?;
+    Some((data, derivative.mime_type))
+}
+
+fn transform(
+    data: Vec<u8>,
+    width: Option<u32>,
+    format: Option<(ImageOutputFormat, &'static str)>,
+) -> anyhow::Result<(Vec<u8>, String)> {
+    let source_format = image::guess_format(&data).ok();
+    if width.is_none() && format.is_none() {
+        let content_type = source_format
+            .map(|format| format.to_mime_type())
+            .unwrap_or("application/octet-stream");
+        return Ok((data, content_type.to_string()));
+    }
+
+    let mut image = image::load_from_memory(&data)?;
+    if let Some(width) = width {
+        if width < image.width() {
+            image = image.resize(width, u32::MAX, FilterType::Lanczos3);
+        }
+    }
+
+    let (output_format, content_type) = match format {
+        Some(format) => format,
+        None => match source_format {
+            Some(image::ImageFormat::Jpeg) => (ImageOutputFormat::Jpeg(85), "image/jpeg"),
+            Some(image::ImageFormat::WebP) => (ImageOutputFormat::WebP, "image/webp"),
+            _ => (ImageOutputFormat::Png, "image/png"),
+        },
+    };
+    let mut encoded = Cursor::new(Vec::new());
+    image.write_to(&mut encoded, output_format)?;
+    Ok((encoded.into_inner(), content_type.to_string()))
+}
+
+#[get("/media/{kind}/{id}")]
+pub async fn get_media(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<dyn Storage>,
+    cache: web::Data<MediaCache>,
+    MediaKind(kind): MediaKind,
+    ImageId(id): ImageId,
+    query: web::Query<MediaQuery>,
+) -> Result<HttpResponse, AppError> {
+    let width = match query.w {
+        Some(width) if width == 0 || width > MAX_WIDTH => {
+            return Err(AppError::bad_request("Invalid width"))
+        }
+        width => width,
+    };
+    let format = match query.format.as_deref() {
+        Some(format) => {
+            Some(output_format(format).ok_or_else(|| AppError::bad_request("Unsupported format"))?)
+        }
+        None => None,
+    };
+
+    let cache_key = format!(
+        "{}/{}?w={}&format={}",
+        kind.to_value(),
+        id,
+        width.unwrap_or_default(),
+        query.format.as_deref().unwrap_or_default()
+    );
+
+    let load = || async {
+        let derivative = match (width, query.format.as_deref()) {
+            (Some(width), Some(format)) => {
+                fetch_derivative(db.as_ref(), storage.as_ref(), kind, id, width, format).await
+            }
+            _ => None,
+        };
+        let (data, content_type) = match derivative {
+            Some(derivative) => derivative,
+            None => {
+                let original = fetch_original(db.as_ref(), storage.as_ref(), kind, id).await?;
+                web::block(move || transform(original, width, format))
+                    .await
+                    .map_err(AppError::internal("Error processing image"))?
+                    .map_err(AppError::internal("Error processing image"))?
+            }
+        };
+        cache
+            .insert(&cache_key, data, content_type)
+            .await
+            .map_err(AppError::internal("Error caching image"))
+    };
+
+    let cached = match cache.get(&cache_key) {
+        Some(media) => NamedFile::open_async(&media.path)
+            .await
+            .ok()
+            .map(|file| (media, file)),
+        None => None,
+    };
+    // A miss, or an entry evicted between the lookup and opening its file.
+    let (media, file) = match cached {
+        Some(cached) => cached,
+        None => {
+            let media = load().await?;
+            let file = NamedFile::open_async(&media.path)
+                .await
+                .map_err(AppError::internal("Error reading cached image"))?;
+            (media, file)
+        }
+    };
+
+    // Serving from disk gives us ETag, Last-Modified and Range handling.
+    let content_type = media
+        .content_type
+        .parse()
+        .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+    let file = file
+        .set_content_type(content_type)
+        .disable_content_disposition();
+    let mut response = file.into_response(&req);
+    response.headers_mut().insert(
+        CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=86400"),
+    );
+    Ok(response)
+}
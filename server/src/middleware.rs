@@ -0,0 +1,109 @@
+use std::future::{ready, Future, Ready};
+use std::time::Instant;
+
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+
+use tracing::{event, info_span, Level, Span};
+
+use crate::error::{AppError, ResponseFormat};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+/// Identifies a request in logs and error responses. A request id sent by a
+/// proxy is kept, otherwise a new one is made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LENGTH
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        valid.then(|| RequestId(value.to_string()))
+    }
+
+    /// Stores the request's id in its extensions, where handlers can extract
+    /// it.
+    pub fn assign(req: &ServiceRequest) -> Self {
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(RequestId::from_header)
+            .unwrap_or_else(|| RequestId(uuid::Uuid::new_v4().to_string()));
+        req.extensions_mut().insert(request_id.clone());
+        request_id
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_else(|| RequestId(String::new()));
+        ready(Ok(request_id))
+    }
+}
+
+/// Logs any error a handler or extractor returned and renders it in the
+/// format the client asked for, tagged with the request id.
+pub fn negotiate_errors(mut res: ServiceResponse, request_id: &RequestId) -> ServiceResponse {
+    if let Ok(value) = HeaderValue::from_str(&request_id.0) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    let Some(error) = res.response().error() else {
+        return res;
+    };
+
+    let fallback;
+    let error = match error.as_error::<AppError>() {
+        Some(error) => error,
+        None => {
+            fallback = AppError::from_actix(error);
+            &fallback
+        }
+    };
+    error.log(&request_id.0);
+    let mut response = error.render(ResponseFormat::of(res.request()), &request_id.0);
+    if let Ok(value) = HeaderValue::from_str(&request_id.0) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    res.into_response(response)
+}
+
+/// The span every log line of a request is recorded in, exported with the
+/// request id so a trace can be found from an error response.
+pub fn request_span(req: &ServiceRequest, request_id: &RequestId) -> Span {
+    info_span!(
+        "request",
+        request_id = %request_id.0,
+        method = %req.method(),
+        path = %req.path(),
+    )
+}
+
+/// Awaits the response, negotiates any error and logs how the request went.
+pub async fn trace_request(
+    response: impl Future<Output = Result<ServiceResponse, actix_web::Error>>,
+    request_id: RequestId,
+) -> Result<ServiceResponse, actix_web::Error> {
+    let started = Instant::now();
+    let response = negotiate_errors(response.await?, &request_id);
+    event!(
+        Level::INFO,
+        status = response.status().as_u16(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "Handled request"
+    );
+    Ok(response)
+}
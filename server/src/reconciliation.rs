@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use actix_web::{get, web};
+use database::reconciliation::{latest_run, ReconciliationReport};
+use prometheus::{IntGauge, Registry};
+use sea_orm::{DatabaseConnection, DbErr};
+
+use crate::admin::Admin;
+use crate::error::AppError;
+use crate::template::ReconciliationTemplate;
+
+const METRICS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Gauges for the findings of the latest reconciliation run. The reconciler
+/// runs in the collector, which only exports its pool gauges, so the server
+/// reads its results back from the database.
+#[derive(Clone)]
+pub struct ReconciliationMetrics {
+    missing_generations: IntGauge,
+    missing_objects: IntGauge,
+    orphaned_objects: IntGauge,
+    last_run_timestamp: IntGauge,
+}
+
+impl ReconciliationMetrics {
+    pub fn register(registry: &Registry) -> prometheus::Result<Self> {
+        let gauge = |name: &str, help: &str| -> prometheus::Result<IntGauge> {
+            let gauge = IntGauge::new(name, help)?;
+            registry.register(Box::new(gauge.clone()))?;
+            Ok(gauge)
+        };
+
+        Ok(Self {
+            missing_generations: gauge(
+                "reconciliation_missing_generations",
+                "Inspiration images that were never generated",
+            )?,
+            missing_objects: gauge(
+                "reconciliation_missing_objects",
+                "Generated images whose object is missing from storage",
+            )?,
+            orphaned_objects: gauge(
+                "reconciliation_orphaned_objects",
+                "Objects in storage that no row refers to",
+            )?,
+            last_run_timestamp: gauge(
+                "reconciliation_last_run_timestamp_seconds",
+                "When the latest reconciliation run finished",
+            )?,
+        })
+    }
+
+    pub async fn refresh(&self, db: &DatabaseConnection) -> Result<(), DbErr> {
+        let Some(run) = latest_run(db).await? else {
+            return Ok(());
+        };
+        let report = ReconciliationReport::from_run(&run)?;
+
+        self.missing_generations
+            .set(report.missing_generations.len() as i64);
+        self.missing_objects
+            .set(report.missing_objects.len() as i64);
+        self.orphaned_objects
+            .set(report.orphaned_objects.len() as i64);
+        self.last_run_timestamp.set(run.finished_at.timestamp());
+        Ok(())
+    }
+
+    /// Keeps the gauges up to date for as long as the server runs.
+    pub fn spawn_refresh(self, db: DatabaseConnection) {
+        tokio::spawn(async move {
+            loop {
+                let _ = self.refresh(&db).await;
+                tokio::time::sleep(METRICS_REFRESH_INTERVAL).await;
+            }
+        });
+    }
+}
+
+#[get("/admin/reconciliation")]
+pub async fn get_reconciliation(
+    _admin: Admin,
+    db: web::Data<DatabaseConnection>,
+) -> Result<ReconciliationTemplate, AppError> {
+    let run = latest_run(db.as_ref())
+        .await
+        .map_err(AppError::internal("Error loading reconciliation run"))?;
+    let report = match &run {
+        Some(run) => ReconciliationReport::from_run(run)
+            .map_err(AppError::internal("Error loading reconciliation run"))?,
+        None => ReconciliationReport::default(),
+    };
+
+    Ok(ReconciliationTemplate { run, report })
+}
@@ -0,0 +1,92 @@
+use actix_web::{get, web};
+use sea_orm::{DatabaseConnection, DbBackend, DbErr, FromQueryResult, Statement};
+
+use crate::error::AppError;
+use crate::template::PromptDriftReportTemplate;
+
+const TOP_CONCEPTS: i64 = 20;
+const MOST_DRIFTED: i64 = 10;
+
+#[derive(Debug, Default, FromQueryResult)]
+pub struct DriftSummary {
+    pub analyzed: i64,
+    pub average_similarity: Option<f64>,
+    pub average_length_ratio: Option<f64>,
+    pub max_length_ratio: Option<f64>,
+}
+
+#[derive(Debug, FromQueryResult)]
+pub struct ConceptCount {
+    pub concept: String,
+    pub occurrences: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+pub struct DriftedImage {
+    pub id: i32,
+    pub prompt_similarity: f64,
+    pub prompt_length_ratio: f64,
+}
+
+async fn concept_counts(db: &DatabaseConnection, column: &str) -> Result<Vec<ConceptCount>, DbErr> {
+    // The column name is one of two constants below, never user input.
+    ConceptCount::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        &format!(
+            r#"SELECT concept, count(*) AS occurrences
+            FROM generated_image, jsonb_array_elements_text(generated_image.{column}) concept
+            GROUP BY concept
+            ORDER BY occurrences DESC, concept
+            LIMIT $1"#
+        ),
+        [TOP_CONCEPTS.into()],
+    ))
+    .all(db)
+    .await
+}
+
+pub async fn prompt_drift_report(
+    db: &DatabaseConnection,
+) -> Result<PromptDriftReportTemplate, DbErr> {
+    let summary = DriftSummary::find_by_statement(Statement::from_string(
+        DbBackend::Postgres,
+        r#"SELECT
+            count(prompt_similarity) AS analyzed,
+            avg(prompt_similarity) AS average_similarity,
+            avg(prompt_length_ratio) AS average_length_ratio,
+            max(prompt_length_ratio) AS max_length_ratio
+        FROM generated_image"#
+            .to_string(),
+    ))
+    .one(db)
+    .await?
+    .unwrap_or_default();
+
+    let most_drifted = DriftedImage::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT id, prompt_similarity, prompt_length_ratio
+        FROM generated_image
+        WHERE prompt_similarity IS NOT NULL AND prompt_length_ratio IS NOT NULL
+        ORDER BY prompt_similarity, id
+        LIMIT $1"#,
+        [MOST_DRIFTED.into()],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(PromptDriftReportTemplate {
+        summary,
+        added_concepts: concept_counts(db, "added_concepts").await?,
+        removed_concepts: concept_counts(db, "removed_concepts").await?,
+        most_drifted,
+    })
+}
+
+#[get("/reports/prompt-drift")]
+pub async fn get_prompt_drift_report(
+    db: web::Data<DatabaseConnection>,
+) -> Result<PromptDriftReportTemplate, AppError> {
+    prompt_drift_report(db.as_ref())
+        .await
+        .map_err(AppError::internal("Error building prompt drift report"))
+}
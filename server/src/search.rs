@@ -0,0 +1,94 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use askama_actix::TemplateToResponse;
+use database::repository::{Pairs, HIGHLIGHT_START, HIGHLIGHT_STOP};
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
+
+use crate::error::{AppError, ResponseFormat};
+use crate::template::SearchResultsTemplate;
+
+const DEFAULT_LIMIT: u64 = 20;
+const MAX_LIMIT: u64 = 100;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SearchQuery {
+    pub q: Option<String>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub generated_image_id: i32,
+    pub inspiration_image_id: i32,
+    /// HTML with matches wrapped in `<mark>`, everything else escaped.
+    pub description_highlight: String,
+    /// HTML with matches wrapped in `<mark>`, everything else escaped.
+    pub revised_prompt_highlight: String,
+    pub rank: f32,
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn highlight_to_html(headline: &str) -> String {
+    escape_html(headline)
+        .replace(HIGHLIGHT_START, "<mark>")
+        .replace(HIGHLIGHT_STOP, "</mark>")
+}
+
+pub async fn search_images(
+    db: &DatabaseConnection,
+    query: &str,
+    limit: u64,
+) -> Result<Vec<SearchResult>, sea_orm::DbErr> {
+    let hits = Pairs::new(db).search(query, limit).await?;
+
+    Ok(hits
+        .into_iter()
+        .map(|hit| SearchResult {
+            generated_image_id: hit.generated_image_id,
+            inspiration_image_id: hit.inspiration_image_id,
+            description_highlight: highlight_to_html(&hit.description),
+            revised_prompt_highlight: highlight_to_html(&hit.revised_prompt),
+            rank: hit.rank,
+        })
+        .collect())
+}
+
+#[get("/search")]
+pub async fn search(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse, AppError> {
+    let q = query.q.as_deref().unwrap_or_default().trim().to_string();
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let results = if q.is_empty() {
+        Vec::new()
+    } else {
+        search_images(db.as_ref(), &q, limit)
+            .await
+            .map_err(AppError::internal("Error searching images"))?
+    };
+
+    // htmx requests and browsers get an HTML fragment, API clients asking for
+    // JSON get JSON.
+    if ResponseFormat::of(&req) == ResponseFormat::Json {
+        return Ok(HttpResponse::Ok().json(results));
+    }
+
+    Ok(SearchResultsTemplate { query: q, results }.to_response())
+}
@@ -0,0 +1,35 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use askama_actix::TemplateToResponse;
+use database::embedding::find_similar;
+use sea_orm::DatabaseConnection;
+
+use crate::error::{AppError, ResponseFormat};
+use crate::extractors::ImageId;
+use crate::template::SimilarImagesTemplate;
+
+const DEFAULT_LIMIT: u64 = 8;
+const MAX_LIMIT: u64 = 50;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SimilarQuery {
+    pub limit: Option<u64>,
+}
+
+#[get("/images/{id}/similar")]
+pub async fn get_similar_images(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    ImageId(id): ImageId,
+    query: web::Query<SimilarQuery>,
+) -> Result<HttpResponse, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let results = find_similar(db.as_ref(), id, limit)
+        .await
+        .map_err(AppError::internal("Error finding similar images"))?;
+
+    if ResponseFormat::of(&req) == ResponseFormat::Json {
+        return Ok(HttpResponse::Ok().json(results));
+    }
+
+    Ok(SimilarImagesTemplate { results }.to_response())
+}
@@ -1,11 +1,29 @@
 use std::net::TcpListener;
+use std::sync::Arc;
 
-use actix_web::{dev::Server, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use actix_web::dev::{Server, Service};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_prom::PrometheusMetricsBuilder;
-use sea_orm::DatabaseConnection;
+use database::costs::{Budget, PriceTable};
+use database::pool::Databases;
+use database::pool_metrics::PoolMetrics;
+use storage::Storage;
+use tracing::Instrument;
 
 use crate::{
+    admin::{add_image_tag, delete_image_tag, get_image_tags, AdminToken},
     api::{get_first_image, get_image_by_id, get_next_image, get_previous_image},
+    collections::get_collection,
+    costs::{get_costs, CostMetrics},
+    error::AppError,
+    files::get_file,
+    media::{get_media, MediaCache},
+    middleware::{request_span, trace_request, RequestId},
+    reconciliation::{get_reconciliation, ReconciliationMetrics},
+    reports::get_prompt_drift_report,
+    search::search,
+    similar::get_similar_images,
+    tags::get_tag,
     template::IndexTemplate,
 };
 
@@ -23,8 +41,15 @@ pub async fn health_check(_req: HttpRequest) -> HttpResponse {
     HttpResponse::Ok().finish()
 }
 
-async fn index() -> impl Responder {
-    IndexTemplate {}
+#[derive(serde::Deserialize)]
+struct IndexQuery {
+    image: Option<i32>,
+}
+
+async fn index(query: web::Query<IndexQuery>) -> impl Responder {
+    IndexTemplate {
+        image_id: query.image,
+    }
 }
 
 pub struct Application {
@@ -35,11 +60,13 @@ pub struct Application {
 impl Application {
     pub async fn build(
         port: u16,
-        db_connection: DatabaseConnection,
+        databases: impl Into<Databases>,
+        storage: Arc<dyn Storage>,
     ) -> Result<Self, anyhow::Error> {
         let address = format!("{}:{}", Application::address(), port);
         let listener = TcpListener::bind(address)?;
-        let server = Application::build_server(listener, db_connection)?;
+        let media_cache = MediaCache::from_env()?;
+        let server = Application::build_server(listener, databases, storage, media_cache)?;
 
         Ok(Self { port, server })
     }
@@ -48,17 +75,43 @@ impl Application {
         self.server.await
     }
 
+    /// Pages only read, so every handler gets the replica when there is one.
     pub fn build_server(
         listener: TcpListener,
-        db_connection: DatabaseConnection,
+        databases: impl Into<Databases>,
+        storage: Arc<dyn Storage>,
+        media_cache: MediaCache,
     ) -> Result<Server, std::io::Error> {
+        let databases = databases.into();
+        let reader = databases.reader().clone();
+        let storage = web::Data::from(storage);
+        let media_cache = web::Data::new(media_cache);
         let prometheus = PrometheusMetricsBuilder::new("api")
             .endpoint("/metrics")
             .build()
             .unwrap();
+        ReconciliationMetrics::register(&prometheus.registry)
+            .map_err(std::io::Error::other)?
+            .spawn_refresh(reader.clone());
+        PoolMetrics::register(&prometheus.registry)
+            .map_err(std::io::Error::other)?
+            .spawn_refresh(databases.clone());
+        // The generator enforces these, the server only reports them.
+        let budget = Budget::from_env().map_err(std::io::Error::other)?;
+        let admin_token = web::Data::new(AdminToken::from_env());
+        let prices = web::Data::new(PriceTable::from_env().map_err(std::io::Error::other)?);
+        CostMetrics::register(&prometheus.registry, budget)
+            .map_err(std::io::Error::other)?
+            .spawn_refresh(reader.clone());
 
         let server = HttpServer::new(move || {
             App::new()
+                .wrap_fn(|req, srv| {
+                    let request_id = RequestId::assign(&req);
+                    let span = request_span(&req, &request_id);
+                    let response = span.in_scope(|| srv.call(req));
+                    trace_request(response, request_id).instrument(span)
+                })
                 .wrap(prometheus.clone())
                 .route("/echo_user_input", web::post().to(echo_user_input))
                 .route("/health_check", web::get().to(health_check))
@@ -67,7 +120,28 @@ impl Application {
                 .service(get_image_by_id)
                 .service(get_next_image)
                 .service(get_previous_image)
-                .app_data(web::Data::new(db_connection.clone()))
+                .service(get_similar_images)
+                .service(get_media)
+                .service(get_file)
+                .service(search)
+                .service(get_prompt_drift_report)
+                .service(get_tag)
+                .service(get_collection)
+                .service(get_reconciliation)
+                .service(get_costs)
+                .service(get_image_tags)
+                .service(add_image_tag)
+                .service(delete_image_tag)
+                .app_data(web::Data::new(reader.clone()))
+                .app_data(web::Data::new(databases.clone()))
+                .app_data(web::Data::new(budget))
+                .app_data(admin_token.clone())
+                .app_data(prices.clone())
+                .app_data(storage.clone())
+                .app_data(media_cache.clone())
+                .app_data(web::QueryConfig::default().error_handler(|error, _| {
+                    AppError::bad_request(format!("Invalid query string: {error}")).into()
+                }))
         })
         .listen(listener)?
         .run();
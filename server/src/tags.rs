@@ -0,0 +1,92 @@
+use actix_web::{get, web};
+use database::entity::tag::{Column as TagColumn, Entity as Tag};
+use database::tags::normalize_tag;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbBackend, EntityTrait, FromQueryResult, QueryFilter,
+    Statement,
+};
+
+use crate::error::AppError;
+use crate::template::TagTemplate;
+
+pub const PAGE_SIZE: u64 = 48;
+
+/// A generated image and the inspiration image it was made from, as shown in
+/// the tag and collection grids.
+#[derive(Debug, FromQueryResult)]
+pub struct PairSummary {
+    pub generated_image_id: i32,
+    pub inspiration_image_id: i32,
+    pub revised_prompt: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct TagQuery {
+    pub page: Option<u64>,
+}
+
+/// The number of rows before `page`, or `None` when it is past any offset
+/// the database accepts.
+pub fn page_offset(page: u64) -> Option<i64> {
+    page.checked_mul(PAGE_SIZE)
+        .and_then(|offset| i64::try_from(offset).ok())
+}
+
+/// Pairs where either image has the tag, so Unsplash tags on the inspiration
+/// image and prompt tags on the generated image both count.
+pub async fn pairs_for_tag(
+    db: &DatabaseConnection,
+    tag_id: i32,
+    offset: i64,
+) -> Result<Vec<PairSummary>, sea_orm::DbErr> {
+    PairSummary::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT
+            generated_image.id AS generated_image_id,
+            generated_image.inspiration_image_id,
+            generated_image.revised_prompt
+        FROM generated_image
+        WHERE EXISTS (
+            SELECT 1 FROM image_tag
+            WHERE image_tag.tag_id = $1
+            AND (
+                (image_tag.image_kind = 'generated' AND image_tag.image_id = generated_image.id)
+                OR (image_tag.image_kind = 'inspiration'
+                    AND image_tag.image_id = generated_image.inspiration_image_id)
+            )
+        )
+        ORDER BY generated_image.id
+        LIMIT $2 OFFSET $3"#,
+        [tag_id.into(), (PAGE_SIZE as i64).into(), offset.into()],
+    ))
+    .all(db)
+    .await
+}
+
+#[get("/tags/{tag}")]
+pub async fn get_tag(
+    db: web::Data<DatabaseConnection>,
+    tag: web::Path<String>,
+    query: web::Query<TagQuery>,
+) -> Result<TagTemplate, AppError> {
+    let page = query.page.unwrap_or_default();
+    let offset = page_offset(page).ok_or_else(|| AppError::bad_request("Page out of range"))?;
+    let name = normalize_tag(&tag).ok_or_else(|| AppError::not_found("Tag not found"))?;
+    let tag = Tag::find()
+        .filter(TagColumn::Name.eq(name))
+        .one(db.as_ref())
+        .await
+        .map_err(AppError::internal("Error reading tag from db"))?
+        .ok_or_else(|| AppError::not_found("Tag not found"))?;
+
+    let pairs = pairs_for_tag(db.as_ref(), tag.id, offset)
+        .await
+        .map_err(AppError::internal("Error reading images from db"))?;
+    let next_page = (pairs.len() as u64 == PAGE_SIZE).then_some(page + 1);
+
+    Ok(TagTemplate {
+        tag: tag.name,
+        pairs,
+        next_page,
+    })
+}
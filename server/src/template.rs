@@ -1,14 +1,174 @@
 use askama_actix::Template;
+use database::embedding::SimilarImage;
 use database::entity::generated_image::Model as GeneratedImageModel;
+use database::entity::image_derivative::Model as ImageDerivativeModel;
 use database::entity::inspiration_image::Model as InspirationImageModel;
+use database::entity::reconciliation_run::Model as ReconciliationRunModel;
+use database::prompt_drift::{DiffKind, DiffSegment};
+use database::reconciliation::ReconciliationReport;
+use database::repository::Pair;
+
+use crate::costs::{DailySpendRow, PriceRow, PriceSpendRow};
+use crate::reports::{ConceptCount, DriftSummary, DriftedImage};
+use crate::search::SearchResult;
+use crate::tags::PairSummary;
 
 #[derive(Template)]
 #[template(path = "index.html")]
-pub struct IndexTemplate;
+pub struct IndexTemplate {
+    /// The generated image to open on, instead of the first one.
+    pub image_id: Option<i32>,
+}
 
 #[derive(Template)]
 #[template(path = "images.html")]
 pub struct GeneratedImageTemplate {
     pub generated_image: GeneratedImageModel,
     pub inspiration_image: InspirationImageModel,
+    pub generated_srcset: Srcset,
+    pub inspiration_srcset: Srcset,
+    pub prompt_diff: Vec<DiffSegment>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+pub struct SearchResultsTemplate {
+    pub query: String,
+    pub results: Vec<SearchResult>,
+}
+
+#[derive(Template)]
+#[template(path = "prompt_drift_report.html")]
+pub struct PromptDriftReportTemplate {
+    pub summary: DriftSummary,
+    pub added_concepts: Vec<ConceptCount>,
+    pub removed_concepts: Vec<ConceptCount>,
+    pub most_drifted: Vec<DriftedImage>,
+}
+
+#[derive(Template)]
+#[template(path = "reconciliation.html")]
+pub struct ReconciliationTemplate {
+    pub run: Option<ReconciliationRunModel>,
+    pub report: ReconciliationReport,
+}
+
+#[derive(Template)]
+#[template(path = "costs.html")]
+pub struct CostsTemplate {
+    pub today_usd: f64,
+    pub month_usd: f64,
+    pub daily_budget_usd: Option<f64>,
+    pub monthly_budget_usd: Option<f64>,
+    /// The budget that is spent, `daily` or `monthly`, if generation is
+    /// paused.
+    pub exceeded: Option<String>,
+    pub days: Vec<DailySpendRow>,
+    pub month_by_price: Vec<PriceSpendRow>,
+    pub prices: Vec<PriceRow>,
+}
+
+#[derive(Template)]
+#[template(path = "similar.html")]
+pub struct SimilarImagesTemplate {
+    pub results: Vec<SimilarImage>,
+}
+
+#[derive(Template)]
+#[template(path = "tag.html")]
+pub struct TagTemplate {
+    pub tag: String,
+    pub pairs: Vec<PairSummary>,
+    pub next_page: Option<u64>,
+}
+
+#[derive(Template)]
+#[template(path = "collection.html")]
+pub struct CollectionTemplate {
+    pub name: String,
+    pub description: Option<String>,
+    pub pairs: Vec<PairSummary>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_image_tags.html")]
+pub struct AdminImageTagsTemplate {
+    pub pair: Pair,
+    pub inspiration_tags: TagEditorTemplate,
+    pub generated_tags: TagEditorTemplate,
+}
+
+#[derive(Template)]
+#[template(path = "tag_editor.html")]
+pub struct TagEditorTemplate {
+    pub generated_image_id: i32,
+    /// `generated` or `inspiration`.
+    pub kind: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "error.html")]
+pub struct ErrorPageTemplate {
+    pub status: u16,
+    pub message: String,
+    pub request_id: String,
+}
+
+#[derive(Template)]
+#[template(path = "error_fragment.html")]
+pub struct ErrorFragmentTemplate {
+    pub status: u16,
+    pub message: String,
+    pub request_id: String,
+}
+
+pub struct SrcsetSource {
+    pub mime_type: String,
+    pub srcset: String,
+}
+
+/// Responsive image sources built from an image's derivatives, served through
+/// the media route at `media_path`. `sources` holds the modern formats for
+/// `<source>` elements and `fallback` the `srcset` for the `<img>` itself.
+#[derive(Default)]
+pub struct Srcset {
+    pub sources: Vec<SrcsetSource>,
+    pub fallback: String,
+}
+
+impl Srcset {
+    pub fn from_derivatives(media_path: &str, derivatives: &[ImageDerivativeModel]) -> Self {
+        let mut srcset = Srcset::default();
+        for format in ["avif", "webp", "jpeg", "png"] {
+            let candidates = derivatives
+                .iter()
+                .filter(|derivative| derivative.format == format)
+                .map(|derivative| {
+                    format!(
+                        "{}?w={}&format={} {}w",
+                        media_path, derivative.width, derivative.format, derivative.width
+                    )
+                })
+                .collect::<Vec<_>>();
+            if candidates.is_empty() {
+                continue;
+            }
+
+            let mime_type = derivatives
+                .iter()
+                .find(|derivative| derivative.format == format)
+                .map(|derivative| derivative.mime_type.clone())
+                .unwrap_or_default();
+            match format {
+                "jpeg" | "png" => srcset.fallback = candidates.join(", "),
+                _ => srcset.sources.push(SrcsetSource {
+                    mime_type,
+                    srcset: candidates.join(", "),
+                }),
+            }
+        }
+        srcset
+    }
 }
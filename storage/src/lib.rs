@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+pub mod local;
+pub mod s3;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+/// Where image bytes live. Keys are `/` separated paths that are stable for a
+/// given object, and `url` returns the address browsers should load it from.
+#[async_trait]
+pub trait Storage: std::fmt::Debug + Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> anyhow::Result<()>;
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>>;
+    async fn exists(&self, key: &str) -> anyhow::Result<bool>;
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+    async fn list(&self, prefix: &str) -> anyhow::Result<Vec<String>>;
+    fn url(&self, key: &str) -> String;
+
+    /// Whether `url` points at the server's `/files` route rather than at the
+    /// backend itself.
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+/// Builds the storage backend selected by `STORAGE_BACKEND` (`s3` or `local`).
+pub fn get_storage() -> Arc<dyn Storage> {
+    match std::env::var("STORAGE_BACKEND") {
+        Ok(value) if value == "local" => {
+            let root = std::env::var("LOCAL_STORAGE_PATH").unwrap_or("./images".to_string());
+            let public_url = std::env::var("STORAGE_PUBLIC_URL").unwrap_or("/files".to_string());
+            Arc::new(LocalStorage::new(root.into(), public_url))
+        }
+        _ => {
+            let s3_access_key = std::env::var("S3_ACCESS_KEY").expect("S3 access key must be set");
+            let s3_secret_key = std::env::var("S3_SECRET_KEY").expect("S3 secret key must be set");
+            let bucket_name =
+                std::env::var("S3_BUCKET").unwrap_or("software-arch-images".to_string());
+            Arc::new(S3Storage::new(bucket_name, s3_access_key, s3_secret_key))
+        }
+    }
+}
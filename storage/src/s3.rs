@@ -0,0 +1,140 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use rusoto_core::{ByteStream, Region, RusotoError};
+use rusoto_credential::StaticProvider;
+use rusoto_s3::{
+    DeleteObjectRequest, GetObjectRequest, HeadObjectRequest, ListObjectsV2Request,
+    PutObjectRequest, S3Client, S3,
+};
+use tokio::io::AsyncReadExt;
+use tracing::{event, instrument, Level};
+
+use crate::Storage;
+
+pub struct S3Storage {
+    client: S3Client,
+    bucket_name: String,
+}
+
+impl std::fmt::Debug for S3Storage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Storage")
+            .field("bucket_name", &self.bucket_name)
+            .finish()
+    }
+}
+
+impl S3Storage {
+    pub fn new(bucket_name: String, access_key: String, secret_key: String) -> Self {
+        event!(Level::INFO, "Authenticating with AWS");
+        let credentials_provider = StaticProvider::new_minimal(access_key, secret_key);
+        let dispatch_provider =
+            rusoto_core::request::HttpClient::new().expect("Failed to create S3 http client");
+        let client = S3Client::new_with(dispatch_provider, credentials_provider, Region::UsEast1);
+
+        Self {
+            client,
+            bucket_name,
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    #[instrument(skip(data))]
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> anyhow::Result<()> {
+        let request = PutObjectRequest {
+            bucket: self.bucket_name.clone(),
+            key: key.to_owned(),
+            content_type: Some(content_type.to_string()),
+            body: Some(ByteStream::from(data)),
+            ..Default::default()
+        };
+
+        event!(Level::INFO, "Uploading object to s3");
+        self.client.put_object(request).await?;
+        Ok(())
+    }
+
+    #[instrument]
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        let request = GetObjectRequest {
+            bucket: self.bucket_name.clone(),
+            key: key.to_owned(),
+            ..Default::default()
+        };
+
+        let object = self.client.get_object(request).await?;
+        let body = object
+            .body
+            .ok_or_else(|| anyhow!("S3 object {key} has no body"))?;
+        let mut data = Vec::new();
+        body.into_async_read().read_to_end(&mut data).await?;
+        Ok(data)
+    }
+
+    #[instrument]
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        let request = HeadObjectRequest {
+            bucket: self.bucket_name.clone(),
+            key: key.to_owned(),
+            ..Default::default()
+        };
+
+        match self.client.head_object(request).await {
+            Ok(_) => Ok(true),
+            // HEAD responses have no body, so a missing key surfaces as an
+            // unparsed 404 rather than `NoSuchKey`.
+            Err(RusotoError::Unknown(response)) if response.status.as_u16() == 404 => Ok(false),
+            Err(e) => Err(anyhow!(e)),
+        }
+    }
+
+    #[instrument]
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let request = DeleteObjectRequest {
+            bucket: self.bucket_name.clone(),
+            key: key.to_owned(),
+            ..Default::default()
+        };
+
+        self.client.delete_object(request).await?;
+        Ok(())
+    }
+
+    #[instrument]
+    async fn list(&self, prefix: &str) -> anyhow::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut continuation_token = None;
+
+        loop {
+            let request = ListObjectsV2Request {
+                bucket: self.bucket_name.clone(),
+                prefix: Some(prefix.to_owned()),
+                continuation_token: continuation_token.clone(),
+                ..Default::default()
+            };
+            let output = self.client.list_objects_v2(request).await?;
+            keys.extend(
+                output
+                    .contents
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|object| object.key),
+            );
+
+            match output.next_continuation_token {
+                Some(token) if output.is_truncated.unwrap_or(false) => {
+                    continuation_token = Some(token)
+                }
+                _ => break,
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("https://{}.s3.amazonaws.com/{}", self.bucket_name, key)
+    }
+}
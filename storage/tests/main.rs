@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+    use storage::{LocalStorage, Storage};
+
+    fn local_storage(name: &str) -> LocalStorage {
+        let root =
+            std::env::temp_dir().join(format!("storage-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        LocalStorage::new(root, "/files".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_local_storage_round_trip() {
+        let storage = local_storage("round-trip");
+
+        storage
+            .put("generated/1/256.webp", vec![1, 2, 3], "image/webp")
+            .await
+            .unwrap();
+
+        assert!(storage.exists("generated/1/256.webp").await.unwrap());
+        assert_eq!(
+            storage.get("generated/1/256.webp").await.unwrap(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            storage.url("generated/1/256.webp"),
+            "/files/generated/1/256.webp"
+        );
+        assert!(storage.is_local());
+
+        storage.delete("generated/1/256.webp").await.unwrap();
+        assert!(!storage.exists("generated/1/256.webp").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_local_storage_lists_by_prefix() {
+        let storage = local_storage("list");
+
+        storage
+            .put("generated/1.png", vec![1], "image/png")
+            .await
+            .unwrap();
+        storage
+            .put("inspiration/1.jpg", vec![1], "image/jpeg")
+            .await
+            .unwrap();
+
+        let keys = storage.list("generated/").await.unwrap();
+        assert_eq!(keys, vec!["generated/1.png".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_local_storage_rejects_path_traversal() {
+        let storage = local_storage("traversal");
+
+        let result = storage.put("../escape.png", vec![1], "image/png").await;
+        assert!(result.is_err());
+    }
+}
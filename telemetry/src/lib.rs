@@ -0,0 +1,104 @@
+//! Logging and tracing setup shared by every service. Logs are JSON lines on
+//! stdout unless `LOG_FORMAT=text`, filtered with `RUST_LOG` (default
+//! `info`). When `OTEL_EXPORTER_OTLP_ENDPOINT` is set, spans are also
+//! exported over OTLP, e.g. to the Jaeger instance in docker-compose.
+
+pub mod metrics;
+
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter};
+
+pub const DEFAULT_LOG_FILTER: &str = "info";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Json,
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelemetryConfig {
+    /// Reported as `service.name` on exported spans.
+    pub service_name: String,
+    pub log_format: LogFormat,
+    /// An `EnvFilter` directive such as `info,sqlx=warn`.
+    pub log_filter: String,
+    pub otlp_endpoint: Option<String>,
+}
+
+impl TelemetryConfig {
+    pub fn from_env(service_name: &str) -> Self {
+        Self::from_lookup(service_name, |name| std::env::var(name).ok())
+    }
+
+    pub fn from_lookup(service_name: &str, lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let lookup = |name: &str| lookup(name).filter(|value| !value.is_empty());
+        let log_format = match lookup("LOG_FORMAT").as_deref() {
+            Some("text") | Some("pretty") => LogFormat::Text,
+            _ => LogFormat::Json,
+        };
+        Self {
+            service_name: lookup("OTEL_SERVICE_NAME").unwrap_or(service_name.to_string()),
+            log_format,
+            log_filter: lookup("RUST_LOG").unwrap_or(DEFAULT_LOG_FILTER.to_string()),
+            otlp_endpoint: lookup("OTEL_EXPORTER_OTLP_ENDPOINT"),
+        }
+    }
+}
+
+/// Flushes exported spans when dropped, so keep it alive until the service
+/// exits.
+#[must_use]
+pub struct TelemetryGuard {
+    exporting: bool,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if self.exporting {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+/// Installs the global subscriber for `service_name` configured from the
+/// environment. Must be called from within a Tokio runtime when exporting.
+pub fn init(service_name: &str) -> anyhow::Result<TelemetryGuard> {
+    init_with(&TelemetryConfig::from_env(service_name))
+}
+
+pub fn init_with(config: &TelemetryConfig) -> anyhow::Result<TelemetryGuard> {
+    let filter = EnvFilter::try_new(&config.log_filter)?;
+    let json = config.log_format == LogFormat::Json;
+
+    let tracer = match &config.otlp_endpoint {
+        Some(endpoint) => Some(
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", config.service_name.clone()),
+                ])))
+                .install_batch(runtime::Tokio)?,
+        ),
+        None => None,
+    };
+    let exporting = tracer.is_some();
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(json.then(|| fmt::layer().json().flatten_event(true)))
+        .with((!json).then(fmt::layer))
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+        .try_init()?;
+
+    Ok(TelemetryGuard { exporting })
+}
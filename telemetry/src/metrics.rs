@@ -0,0 +1,64 @@
+//! A `/metrics` endpoint for services without an HTTP server of their own.
+//! It listens on `METRICS_ADDR` (default `0.0.0.0:9000`) and answers every
+//! request with the registry in the Prometheus text format.
+
+pub use prometheus::Registry;
+
+use prometheus::{Encoder, TextEncoder};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+pub const DEFAULT_METRICS_ADDR: &str = "0.0.0.0:9000";
+
+pub fn metrics_addr_from_env() -> String {
+    std::env::var("METRICS_ADDR")
+        .ok()
+        .filter(|addr| !addr.is_empty())
+        .unwrap_or(DEFAULT_METRICS_ADDR.to_string())
+}
+
+/// Binds `addr` and serves `registry` in the background.
+pub async fn serve(registry: Registry, addr: &str) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("Error accepting metrics connection: {e}");
+                    continue;
+                }
+            };
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                if let Err(e) = respond(stream, &registry).await {
+                    tracing::debug!("Error serving metrics: {e}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+async fn respond(stream: TcpStream, registry: &Registry) -> anyhow::Result<()> {
+    let mut stream = BufReader::new(stream);
+    // The request itself doesn't matter, read its head and answer.
+    let mut line = String::new();
+    while stream.read_line(&mut line).await? > 0 && line != "\r\n" && line != "\n" {
+        line.clear();
+    }
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder.encode(&registry.gather(), &mut body)?;
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        encoder.format_type(),
+        body.len()
+    );
+    let stream = stream.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
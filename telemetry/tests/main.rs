@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use telemetry::metrics::{serve, Registry};
+    use telemetry::{LogFormat, TelemetryConfig, DEFAULT_LOG_FILTER};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    #[test]
+    fn test_config_defaults_to_json_logs_without_exporting() {
+        let config = TelemetryConfig::from_lookup("server", |_| None);
+
+        assert_eq!(config.service_name, "server");
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.log_filter, DEFAULT_LOG_FILTER);
+        assert_eq!(config.otlp_endpoint, None);
+    }
+
+    #[test]
+    fn test_config_from_environment() {
+        let env = HashMap::from([
+            ("LOG_FORMAT", "text"),
+            ("RUST_LOG", "debug,sqlx=warn"),
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://jaeger:4317"),
+            ("OTEL_SERVICE_NAME", ""),
+        ]);
+        let config = TelemetryConfig::from_lookup("image_generator", |name| {
+            env.get(name).map(|value| value.to_string())
+        });
+
+        // Empty variables are treated as unset.
+        assert_eq!(config.service_name, "image_generator");
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert_eq!(config.log_filter, "debug,sqlx=warn");
+        assert_eq!(config.otlp_endpoint.as_deref(), Some("http://jaeger:4317"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_are_served_over_http() {
+        let registry = Registry::new();
+        let gauge = prometheus::IntGauge::new("db_pool_connections", "Open connections").unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        gauge.set(3);
+        serve(registry, "127.0.0.1:19123").await.unwrap();
+
+        let mut stream = TcpStream::connect("127.0.0.1:19123").await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("db_pool_connections 3"));
+    }
+}